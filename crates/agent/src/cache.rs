@@ -0,0 +1,206 @@
+use crate::{
+    constants,
+    handler::{ExecuteInfo, ExecuteLimits, HandlerError},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+type CacheKey = ([u8; 32], usize);
+
+/// Runs of finished test cases, keyed by request fingerprint and case index
+///
+/// Lets a rejudge that only changes limits re-evaluate verdicts without running the program
+/// again. Any change to the source, compiler options, seed or case data changes the
+/// [`shared::rpc::JudgeRequest::fingerprint`] and misses; runs stopped by a limit are never cached.
+#[derive(Debug)]
+pub struct OutputCache {
+    capacity_bytes: usize,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    runs: HashMap<CacheKey, CachedRun>,
+    size_bytes: usize,
+}
+
+#[derive(Debug)]
+struct CachedRun {
+    limits: ExecuteLimits,
+    info: ExecuteInfo,
+}
+
+impl Default for OutputCache {
+    fn default() -> Self {
+        Self::new(constants::DEFAULT_OUTPUT_CACHE_BYTES)
+    }
+}
+
+impl OutputCache {
+    /// Cache holding up to `capacity_bytes` of program output, later runs are not stored
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Replay the cached run of a case under new `limits`
+    ///
+    /// None on a miss or when the limits could change how the program behaves (stack limit, pids
+    /// and disk quota). Tighter time and output limits are checked against the recorded run, as
+    /// the handler would have; cpu time and memory are left to the caller like for a fresh run.
+    pub fn replay(
+        &self,
+        fingerprint: [u8; 32],
+        case_index: usize,
+        limits: &ExecuteLimits,
+    ) -> Option<Result<ExecuteInfo, HandlerError>> {
+        let entries = self.inner.lock().unwrap();
+        let run = entries.runs.get(&(fingerprint, case_index))?;
+
+        if run.limits.stack_kib != limits.stack_kib
+            || run.limits.pids != limits.pids
+            || run.limits.disk_kib != limits.disk_kib
+        {
+            return None;
+        }
+
+        let info = &run.info;
+        if info.resource_usage.real_time_ms > limits.real_time_ms {
+            return Some(Err(HandlerError::TimeLimitExceeded));
+        }
+        if info.stdout.len() > limits.stdout_bytes || info.stderr.len() > limits.stderr_bytes {
+            return Some(Err(HandlerError::OutputLimitExceeded));
+        }
+
+        Some(Ok(info.clone()))
+    }
+
+    /// Remember a run that finished within `limits`, see [`OutputCache::replay`]
+    pub fn insert(
+        &self,
+        fingerprint: [u8; 32],
+        case_index: usize,
+        limits: &ExecuteLimits,
+        info: &ExecuteInfo,
+    ) {
+        let size = info.stdout.len() + info.stderr.len();
+        let mut entries = self.inner.lock().unwrap();
+        if entries.size_bytes + size > self.capacity_bytes {
+            return;
+        }
+
+        let run = CachedRun {
+            limits: *limits,
+            info: info.clone(),
+        };
+        if let Some(old) = entries.runs.insert((fingerprint, case_index), run) {
+            entries.size_bytes -= old.info.stdout.len() + old.info.stderr.len();
+        }
+        entries.size_bytes += size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::ResourceUsage;
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    const FINGERPRINT: [u8; 32] = [1; 32];
+
+    fn limits() -> ExecuteLimits {
+        ExecuteLimits {
+            time_ms: 1000,
+            real_time_ms: 2000,
+            memory_kib: 64 * 1024,
+            stack_kib: 64 * 1024,
+            stdout_bytes: 1024,
+            stderr_bytes: 1024,
+            disk_kib: None,
+            pids: 16,
+        }
+    }
+
+    fn info() -> ExecuteInfo {
+        ExecuteInfo {
+            status_code: ExitStatus::from_raw(0),
+            signal: None,
+            stdout: b"42\n".to_vec(),
+            stderr: String::new(),
+            resource_usage: ResourceUsage {
+                memory_kib: 1024,
+                real_time_ms: 500,
+                cpu_time_ms: 400,
+                max_pids: 1,
+            },
+        }
+    }
+
+    fn cache() -> OutputCache {
+        let cache = OutputCache::default();
+        cache.insert(FINGERPRINT, 0, &limits(), &info());
+        cache
+    }
+
+    #[test]
+    fn replays_the_same_case() {
+        let replayed = cache().replay(FINGERPRINT, 0, &limits());
+
+        assert_eq!(replayed.unwrap().unwrap().stdout, b"42\n");
+    }
+
+    #[test]
+    fn misses_other_requests_and_cases() {
+        let cache = cache();
+
+        assert!(cache.replay([2; 32], 0, &limits()).is_none());
+        assert!(cache.replay(FINGERPRINT, 1, &limits()).is_none());
+    }
+
+    #[test]
+    fn misses_when_limits_could_change_the_run() {
+        let cache = cache();
+        let stack = ExecuteLimits {
+            stack_kib: 8 * 1024,
+            ..limits()
+        };
+        let pids = ExecuteLimits {
+            pids: 1,
+            ..limits()
+        };
+
+        assert!(cache.replay(FINGERPRINT, 0, &stack).is_none());
+        assert!(cache.replay(FINGERPRINT, 0, &pids).is_none());
+    }
+
+    #[test]
+    fn checks_tighter_limits_against_the_recorded_run() {
+        let cache = cache();
+        let real_time = ExecuteLimits {
+            real_time_ms: 100,
+            ..limits()
+        };
+        let stdout = ExecuteLimits {
+            stdout_bytes: 1,
+            ..limits()
+        };
+
+        assert!(matches!(
+            cache.replay(FINGERPRINT, 0, &real_time),
+            Some(Err(HandlerError::TimeLimitExceeded))
+        ));
+        assert!(matches!(
+            cache.replay(FINGERPRINT, 0, &stdout),
+            Some(Err(HandlerError::OutputLimitExceeded))
+        ));
+    }
+
+    #[test]
+    fn stores_nothing_beyond_its_capacity() {
+        let cache = OutputCache::new(2);
+        cache.insert(FINGERPRINT, 0, &limits(), &info());
+
+        assert!(cache.replay(FINGERPRINT, 0, &limits()).is_none());
+    }
+}
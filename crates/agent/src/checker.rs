@@ -0,0 +1,247 @@
+//! Custom checkers (special judges), replacing the built-in output comparison
+//!
+//! A checker is run once per test case as `checker <input> <expected> <actual>`, each argument
+//! being the path of a file holding that data, with an empty stdin. Its exit code follows
+//! testlib, see [`shared::rpc::Checker`]: 0 accepts, 2 is a presentation error, 3 is a failure of
+//! the checker and any other code rejects the output, its stdout becoming the wrong answer's
+//! hint. Exiting 3, being killed by a signal or hitting a limit is a [`JudgeError::Checker`]
+//! failure, never blamed on the submission.
+
+use crate::{
+    constants,
+    engine::JudgeError,
+    handler::{CompileLimits, ExecuteInfo, ExecuteLimits, HandlerError, WorkDirGuard, sandbox},
+    seccomp::SeccompProfile,
+};
+use shared::rpc::{Checker, CompilerOptions};
+use std::{path::PathBuf, time::Duration};
+use tokio::process::Command;
+
+/// Checker ready to run, with its own work directory
+///
+/// The work directory is removed when dropped as well, see [`PreparedChecker::cleanup`] to
+/// report failures.
+#[derive(Debug)]
+pub struct PreparedChecker {
+    context: WorkDirGuard,
+}
+
+/// Verdict of a checker on one output
+#[derive(Debug)]
+pub enum CheckerVerdict {
+    Accepted,
+    Rejected { message: Option<String> },
+    PresentationError,
+}
+
+impl PreparedChecker {
+    /// Compile the checker if needed, for the request with `request_id`
+    ///
+    /// The work directory is removed again on every failure.
+    pub async fn prepare(checker: &Checker, request_id: usize) -> Result<Self, JudgeError> {
+        let mut context = match checker {
+            Checker::Executable(path) => {
+                let context = sandbox::prepare("", "checker").await.map_err(failed)?;
+                let mut context = WorkDirGuard::new(context);
+                context.executable_file = PathBuf::from(path);
+                context.request_id = Some(request_id);
+                context
+            }
+            Checker::CppSource(source_code) => {
+                let context = sandbox::prepare(source_code, "checker.cpp")
+                    .await
+                    .map_err(failed)?;
+                let mut context = WorkDirGuard::new(context);
+                context.request_id = Some(request_id);
+                // Default options carry no extra flags, so the command is always built
+                let command = sandbox::gcc_command(
+                    "g++",
+                    &context,
+                    &CompilerOptions::default(),
+                    &[],
+                    &[],
+                    true,
+                )
+                .map_err(failed)?;
+                let info = sandbox::compile(command, "checker", &context, &Self::compile_limits())
+                    .await
+                    .map_err(failed)?;
+                if !info.status_code.success() {
+                    return Err(JudgeError::Checker(format!(
+                        "Checker does not compile:\n{}",
+                        info.stderr
+                    )));
+                }
+                context
+            }
+        };
+        // Trusted, and keeping the agent's user leaves its work directory unreadable to the
+        // judged programs, test data included
+        context.unprivileged = false;
+
+        Ok(Self { context })
+    }
+
+    /// Limits checker sources are compiled under
+    pub fn compile_limits() -> CompileLimits {
+        CompileLimits::default()
+    }
+
+    /// Judge `actual` output of the case with `input` and `expected` output
+    pub async fn check(
+        &self,
+        input: &str,
+        expected: &str,
+        actual: &[u8],
+    ) -> Result<CheckerVerdict, JudgeError> {
+        let dir = &self.context.work_dir;
+        let files = [
+            (dir.join("input.txt"), input.as_bytes()),
+            (dir.join("expected.txt"), expected.as_bytes()),
+            (dir.join("actual.txt"), actual),
+        ];
+        let mut command = Command::new(&self.context.executable_file);
+        for (path, data) in &files {
+            tokio::fs::write(path, data)
+                .await
+                .map_err(|e| failed(e.into()))?;
+            command.arg(path);
+        }
+
+        let limits = ExecuteLimits {
+            time_ms: constants::DEFAULT_CHECKER_TIME_LIMIT_MS,
+            real_time_ms: constants::DEFAULT_CHECKER_TIME_LIMIT_MS,
+            memory_kib: constants::DEFAULT_CHECKER_MEMORY_LIMIT_KIB,
+            stack_kib: constants::DEFAULT_CHECKER_MEMORY_LIMIT_KIB,
+            stdout_bytes: 64 * 1024,
+            stderr_bytes: 64 * 1024,
+            disk_kib: None,
+            pids: constants::DEFAULT_PID_LIMIT,
+        };
+        // Checkers read their argument files, which the strict filter would forbid
+        let info = sandbox::execute(
+            command,
+            "checker",
+            &self.context,
+            "",
+            &limits,
+            Duration::from_millis(constants::DEFAULT_MEMORY_SAMPLE_INTERVAL_MS),
+            SeccompProfile::Compile,
+        )
+        .await
+        .map_err(failed)?;
+
+        verdict(&info)
+    }
+
+    /// Remove the work directory now, reporting failures
+    pub async fn cleanup(self) -> Result<(), JudgeError> {
+        let context = self.context.keep();
+        sandbox::cleanup(&context).await.map_err(failed)
+    }
+}
+
+/// Interpret the exit of a checker run
+fn verdict(info: &ExecuteInfo) -> Result<CheckerVerdict, JudgeError> {
+    match info.status_code.code() {
+        Some(0) => Ok(CheckerVerdict::Accepted),
+        Some(2) => Ok(CheckerVerdict::PresentationError),
+        Some(code) if code != 3 => {
+            let message = info.stdout_lossy();
+            let message = message.trim();
+            Ok(CheckerVerdict::Rejected {
+                message: (!message.is_empty()).then(|| message.into()),
+            })
+        }
+        _ => Err(JudgeError::Checker(format!(
+            "Checker exited with {}:\n{}",
+            info.status_code, info.stderr
+        ))),
+    }
+}
+
+fn failed(err: HandlerError) -> JudgeError {
+    JudgeError::Checker(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::ResourceUsage;
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    fn run(wait_status: i32, stdout: &str) -> ExecuteInfo {
+        ExecuteInfo {
+            status_code: ExitStatus::from_raw(wait_status),
+            signal: ExitStatus::from_raw(wait_status).signal(),
+            stdout: stdout.into(),
+            stderr: "checker stderr".into(),
+            resource_usage: ResourceUsage {
+                memory_kib: 0,
+                real_time_ms: 0,
+                cpu_time_ms: 0,
+                max_pids: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn follows_testlib_exit_codes() {
+        assert!(matches!(verdict(&run(0, "")), Ok(CheckerVerdict::Accepted)));
+        assert!(matches!(
+            verdict(&run(2 << 8, "")),
+            Ok(CheckerVerdict::PresentationError)
+        ));
+        for code in [1, 4, 7, 255] {
+            assert!(matches!(
+                verdict(&run(code << 8, "")),
+                Ok(CheckerVerdict::Rejected { message: None })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejections_carry_the_checker_stdout() {
+        let Ok(CheckerVerdict::Rejected { message }) = verdict(&run(1 << 8, "  wrong sum\n"))
+        else {
+            panic!("Expected a rejection");
+        };
+        assert_eq!(message.as_deref(), Some("wrong sum"));
+    }
+
+    #[test]
+    fn failures_of_the_checker_are_not_blamed_on_the_submission() {
+        for wait_status in [3 << 8, libc::SIGSEGV] {
+            let Err(JudgeError::Checker(message)) = verdict(&run(wait_status, "")) else {
+                panic!("Expected a checker failure");
+            };
+            assert!(message.ends_with("checker stderr"));
+        }
+    }
+
+    /// Whether a work directory holding `file` with `content` is left
+    fn work_dir_left(file: &str, content: &str) -> bool {
+        std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(constants::RESOURCE_PREFIX)
+            })
+            .any(|entry| {
+                std::fs::read_to_string(entry.path().join(file)).is_ok_and(|c| c == content)
+            })
+    }
+
+    #[tokio::test]
+    async fn failed_compiles_leave_no_work_dir_behind() {
+        // Fails to compile where cgroups work, and fails setting up the compile elsewhere
+        let source = "#error failed checker compile\n";
+        let result = PreparedChecker::prepare(&Checker::CppSource(source.into()), 1).await;
+
+        assert!(matches!(result, Err(JudgeError::Checker(_))));
+        assert!(!work_dir_left("checker.cpp", source));
+    }
+}
@@ -0,0 +1,383 @@
+use shared::rpc::{CompareMode, CompareOptions};
+use std::borrow::Cow;
+
+/// Why two outputs differ, beyond the outputs themselves
+#[derive(Debug, Clone, Default)]
+pub struct Mismatch {
+    pub hint: Option<String>,
+}
+
+impl Mismatch {
+    fn hint(hint: String) -> Self {
+        Self { hint: Some(hint) }
+    }
+}
+
+/// Normalize an output according to the comparison mode
+///
+/// Numeric mode only needs the surrounding whitespace gone, tokens are split when comparing.
+pub fn normalize(output: &str, mode: CompareMode) -> Cow<'_, str> {
+    match mode {
+        CompareMode::Trimmed | CompareMode::Numeric { .. } => Cow::Borrowed(output.trim()),
+        CompareMode::Exact => Cow::Borrowed(output),
+        CompareMode::LineTrimmed => {
+            // `lines` also strips a `\r` before each `\n`
+            let mut lines: Vec<&str> = output.lines().map(str::trim_end).collect();
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+
+            Cow::Owned(lines.join("\n"))
+        }
+    }
+}
+
+/// Compare normalized outputs according to the request's comparison options
+///
+/// Case folding happens after normalization, so it never changes what counts as whitespace
+pub fn outputs_match(
+    expected: &str,
+    actual: &str,
+    options: &CompareOptions,
+) -> Result<(), Mismatch> {
+    if let CompareMode::Numeric {
+        int_exact,
+        float_eps,
+    } = options.mode
+    {
+        return numbers_match(expected, actual, int_exact, float_eps);
+    }
+
+    let is_match = if options.case_sensitive {
+        expected == actual
+    } else {
+        expected.to_lowercase() == actual.to_lowercase()
+    };
+
+    if is_match {
+        Ok(())
+    } else {
+        Err(Mismatch::default())
+    }
+}
+
+/// Compare the program's raw stdout with an expected output, normalizing both first
+///
+/// Only [`CompareMode::Exact`] looks at the bytes, other modes compare the lossy UTF-8 text.
+pub fn stdout_matches(
+    expected: &str,
+    stdout: &[u8],
+    options: &CompareOptions,
+) -> Result<(), Mismatch> {
+    if options.mode == CompareMode::Exact {
+        return bytes_match(expected.as_bytes(), stdout, options.case_sensitive);
+    }
+
+    let stdout = String::from_utf8_lossy(stdout);
+    let expected = normalize(expected, options.mode);
+    let actual = normalize(&stdout, options.mode);
+    outputs_match(&expected, &actual, options)
+}
+
+/// Compare the program's raw stdout with the expected output, then with each accepted one
+///
+/// On a mismatch with all of them, the mismatch with `expected` is returned, see
+/// [`shared::rpc::TestCase::accepted_outputs`].
+pub fn stdout_matches_any(
+    expected: &str,
+    accepted: &[impl AsRef<str>],
+    stdout: &[u8],
+    options: &CompareOptions,
+) -> Result<(), Mismatch> {
+    let mismatch = match stdout_matches(expected, stdout, options) {
+        Ok(()) => return Ok(()),
+        Err(mismatch) => mismatch,
+    };
+
+    if accepted
+        .iter()
+        .any(|output| stdout_matches(output.as_ref(), stdout, options).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(mismatch)
+    }
+}
+
+/// Whether stderr holds the expected output, both trimmed whatever the comparison mode
+///
+/// Backs [`CompareOptions::stderr_hint`], only case sensitivity is taken from `options`.
+pub fn stderr_matches(expected: &str, stderr: &str, options: &CompareOptions) -> bool {
+    let trimmed = CompareOptions {
+        mode: CompareMode::Trimmed,
+        ..options.clone()
+    };
+
+    outputs_match(expected.trim(), stderr.trim(), &trimmed).is_ok()
+}
+
+/// Whether two outputs are equal once whitespace is ignored
+///
+/// Runs of spaces, tabs and line breaks (`\n` or `\r\n`) all separate tokens the same way, and
+/// leading, trailing and blank-line whitespace is dropped. Outputs that mismatch but pass this
+/// check are presentation errors.
+pub fn same_tokens(expected: &str, actual: &str, options: &CompareOptions) -> bool {
+    if options.case_sensitive {
+        expected.split_whitespace().eq(actual.split_whitespace())
+    } else {
+        let (expected, actual) = (expected.to_lowercase(), actual.to_lowercase());
+        expected.split_whitespace().eq(actual.split_whitespace())
+    }
+}
+
+fn bytes_match(expected: &[u8], actual: &[u8], case_sensitive: bool) -> Result<(), Mismatch> {
+    let same = |(expected, actual): (&u8, &u8)| {
+        if case_sensitive {
+            expected == actual
+        } else {
+            expected.eq_ignore_ascii_case(actual)
+        }
+    };
+
+    match expected.iter().zip(actual).position(|pair| !same(pair)) {
+        Some(offset) => Err(Mismatch::hint(format!("First difference at byte {offset}"))),
+        None if expected.len() != actual.len() => Err(Mismatch::hint(format!(
+            "Expected {} bytes, found {}",
+            expected.len(),
+            actual.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn numbers_match(
+    expected: &str,
+    actual: &str,
+    int_exact: bool,
+    float_eps: f64,
+) -> Result<(), Mismatch> {
+    let expected: Vec<&str> = expected.split_whitespace().collect();
+    let actual: Vec<&str> = actual.split_whitespace().collect();
+
+    if expected.len() != actual.len() {
+        return Err(Mismatch::hint(format!(
+            "Expected {} numbers, found {}",
+            expected.len(),
+            actual.len()
+        )));
+    }
+
+    for (index, (expected, actual)) in expected.into_iter().zip(actual).enumerate() {
+        let position = index + 1;
+
+        if int_exact
+            && let (Ok(expected), Ok(actual)) = (expected.parse::<i128>(), actual.parse::<i128>())
+        {
+            if expected != actual {
+                return Err(Mismatch::hint(format!(
+                    "Number {position} differs: expected {expected}, found {actual}"
+                )));
+            }
+            continue;
+        }
+
+        let Ok(expected_value) = expected.parse::<f64>() else {
+            return Err(Mismatch::hint(format!(
+                "Expected output token {position} \"{expected}\" is not a number"
+            )));
+        };
+        let Ok(actual_value) = actual.parse::<f64>() else {
+            return Err(Mismatch::hint(format!(
+                "Token {position} \"{actual}\" is not a number"
+            )));
+        };
+
+        // Handles infinities, whose difference is NaN
+        if expected_value == actual_value {
+            continue;
+        }
+
+        let difference = (expected_value - actual_value).abs();
+        if !(difference <= float_eps || difference <= float_eps * expected_value.abs()) {
+            return Err(Mismatch::hint(format!(
+                "Number {position} differs: expected {expected}, found {actual}"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(mode: CompareMode, case_sensitive: bool) -> CompareOptions {
+        CompareOptions {
+            mode,
+            case_sensitive,
+            ..CompareOptions::default()
+        }
+    }
+
+    #[test]
+    fn folds_case_only_when_insensitive() {
+        let sensitive = options(CompareMode::Trimmed, true);
+        let insensitive = options(CompareMode::Trimmed, false);
+
+        assert!(stdout_matches("YES", b"yes\n", &sensitive).is_err());
+        assert!(stdout_matches("YES", b"yes\n", &insensitive).is_ok());
+        assert!(stdout_matches("Straße", "STRASSE".as_bytes(), &insensitive).is_err());
+    }
+
+    #[test]
+    fn exact_mode_folds_ascii_letters_only() {
+        let insensitive = options(CompareMode::Exact, false);
+
+        assert!(stdout_matches("Yes\n", b"yES\n", &insensitive).is_ok());
+        assert!(stdout_matches("É", "é".as_bytes(), &insensitive).is_err());
+        assert!(stdout_matches("Yes\n", b"Yes", &insensitive).is_err());
+    }
+
+    #[test]
+    fn line_trimmed_strips_line_ends_and_trailing_blank_lines() {
+        assert_eq!(
+            normalize("a \r\n  b\t\r\n\r\n\n", CompareMode::LineTrimmed),
+            "a\n  b"
+        );
+        assert_eq!(normalize("", CompareMode::LineTrimmed), "");
+    }
+
+    #[test]
+    fn line_trimmed_keeps_leading_whitespace_and_inner_blank_lines() {
+        let line_trimmed = options(CompareMode::LineTrimmed, true);
+
+        assert!(stdout_matches("1\n\n2\n", b"1  \r\n\r\n2", &line_trimmed).is_ok());
+        assert!(stdout_matches("1\n2", b" 1\n2", &line_trimmed).is_err());
+        assert!(stdout_matches("1\n\n2", b"1\n2", &line_trimmed).is_err());
+    }
+
+    #[test]
+    fn numeric_mode_compares_values_not_spelling() {
+        assert!(numbers_match("1000 0 2.5", "1e3 -0 2.50", false, 1e-9).is_ok());
+        assert!(numbers_match("inf", "inf", false, 1e-9).is_ok());
+        assert!(numbers_match("nan", "nan", false, 1e-9).is_err());
+    }
+
+    #[test]
+    fn numeric_mode_accepts_absolute_or_relative_error() {
+        assert!(numbers_match("0.5", "0.5000001", false, 1e-6).is_ok());
+        assert!(numbers_match("1000000", "1000000.5", false, 1e-6).is_ok());
+        assert!(numbers_match("0.5", "0.501", false, 1e-6).is_err());
+    }
+
+    #[test]
+    fn numeric_mode_compares_integers_exactly_if_asked() {
+        // Beyond the 53 bits of precision of a float
+        let (expected, actual) = ("9007199254740993", "9007199254740992");
+
+        assert!(numbers_match(expected, actual, false, 0.0).is_ok());
+        assert!(numbers_match(expected, actual, true, 0.0).is_err());
+        assert!(numbers_match("1000", "1e3", true, 0.0).is_ok());
+    }
+
+    #[test]
+    fn numeric_mode_hints_at_the_offending_token() {
+        let hint = |expected, actual| {
+            numbers_match(expected, actual, true, 1e-6)
+                .unwrap_err()
+                .hint
+        };
+
+        assert_eq!(hint("1 2", "1"), Some("Expected 2 numbers, found 1".into()));
+        assert_eq!(
+            hint("1 2", "1 x"),
+            Some("Token 2 \"x\" is not a number".into())
+        );
+        assert_eq!(
+            hint("1 2", "1 3"),
+            Some("Number 2 differs: expected 2, found 3".into())
+        );
+    }
+
+    #[test]
+    fn same_tokens_ignores_layout_only() {
+        let sensitive = options(CompareMode::Exact, true);
+
+        assert!(same_tokens("1 2\n3\n", "1\r\n2   3", &sensitive));
+        assert!(same_tokens("a b", "\n\na\tb\n\n", &sensitive));
+        assert!(!same_tokens("1 2", "12", &sensitive));
+        assert!(!same_tokens("a b", "A B", &sensitive));
+        assert!(same_tokens(
+            "a b",
+            "A\nB",
+            &options(CompareMode::Exact, false)
+        ));
+    }
+
+    #[test]
+    fn stderr_is_compared_trimmed_in_every_mode() {
+        let numeric = CompareMode::Numeric {
+            int_exact: true,
+            float_eps: 1e-6,
+        };
+
+        assert!(stderr_matches(
+            "42",
+            "42\n",
+            &options(CompareMode::Exact, true)
+        ));
+        assert!(stderr_matches("1 2", " 1 2\n", &options(numeric, true)));
+        assert!(!stderr_matches("1 2", "1  2", &options(numeric, true)));
+        assert!(stderr_matches(
+            "Yes",
+            "YES\n",
+            &options(CompareMode::Exact, false)
+        ));
+        assert!(!stderr_matches(
+            "Yes",
+            "YES",
+            &options(CompareMode::Trimmed, true)
+        ));
+    }
+
+    #[test]
+    fn crlf_output_is_a_presentation_error_in_exact_mode() {
+        let exact = options(CompareMode::Exact, true);
+        let stdout = b"1\r\n2\r\n";
+
+        assert!(stdout_matches("1\n2\n", stdout, &exact).is_err());
+        assert!(same_tokens(
+            "1\n2\n",
+            &String::from_utf8_lossy(stdout),
+            &exact
+        ));
+    }
+
+    #[test]
+    fn any_accepted_output_matches() {
+        let trimmed = options(CompareMode::Trimmed, true);
+        let accepted = ["2 1", "1 2 3"];
+
+        assert!(stdout_matches_any("1 2", &accepted, b"1 2\n", &trimmed).is_ok());
+        assert!(stdout_matches_any("1 2", &accepted, b"1 2 3\n", &trimmed).is_ok());
+        assert!(stdout_matches_any("1 2", &accepted, b"3 2 1\n", &trimmed).is_err());
+    }
+
+    #[test]
+    fn mismatch_with_all_outputs_is_against_the_expected_one() {
+        let numeric = options(
+            CompareMode::Numeric {
+                int_exact: true,
+                float_eps: 0.0,
+            },
+            true,
+        );
+
+        let mismatch = stdout_matches_any("1 2", &["1 2 3"], b"1 3", &numeric).unwrap_err();
+        assert_eq!(
+            mismatch.hint,
+            Some("Number 2 differs: expected 2, found 3".into())
+        );
+    }
+}
@@ -1,8 +1,62 @@
 /// Default vsock port for agent communication
 pub const DEFAULT_VSOCK_PORT: u32 = 9999;
 
+/// Largest accepted request frame in bytes (64MB)
+///
+/// Requests embed their source and inline case data, large cases belong in the case store.
+pub const MAX_REQUEST_FRAME_BYTES: u32 = 64 * 1024 * 1024;
+
 /// Default compile time limit in milliseconds
 pub const DEFAULT_COMPILE_TIME_LIMIT_MS: u64 = 60_000;
 
 /// Default compile memory limit in KiB (256MB)
 pub const DEFAULT_COMPILE_MEMORY_LIMIT_KIB: u64 = 256 * 1024;
+
+/// Default limit on live processes and threads of a judged program
+pub const DEFAULT_PID_LIMIT: u64 = 16;
+
+/// Wall-clock time limit of a test case by default, as a multiple of its cpu time limit
+pub const DEFAULT_REAL_TIME_FACTOR: u64 = 2;
+
+/// Threads a JVM starts on its own, allowed on top of the pid limit of Java programs
+pub const DEFAULT_JVM_RUNTIME_THREADS: u64 = 16;
+
+/// Memory in KiB a JVM uses besides its heap (class metadata, JIT code, thread stacks), allowed on
+/// top of the memory limit of Java programs
+pub const DEFAULT_JVM_RUNTIME_MEMORY_KIB: u64 = 64 * 1024;
+
+/// Default checker time limit in milliseconds, per test case
+pub const DEFAULT_CHECKER_TIME_LIMIT_MS: u64 = 10_000;
+
+/// Default checker memory limit in KiB (256MB)
+pub const DEFAULT_CHECKER_MEMORY_LIMIT_KIB: u64 = 256 * 1024;
+
+/// Time allowed per test case on top of its time limit, covering spawning and cgroup setup
+pub const REQUEST_CASE_OVERHEAD_MS: u64 = 1_000;
+
+/// Hard ceiling in milliseconds on judging a single request, past which its task is aborted
+///
+/// Applies whatever the request's limits, a request whose cases add up to more ends with a
+/// timeout rather than running past it.
+pub const MAX_REQUEST_TIME_MS: u64 = 10 * 60 * 1000;
+
+/// Default interval in milliseconds between memory usage samples during execution
+pub const DEFAULT_MEMORY_SAMPLE_INTERVAL_MS: u64 = 10;
+
+/// Default capacity in bytes of the output cache used for rejudges (64MB)
+pub const DEFAULT_OUTPUT_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Bytes of stdout kept per case in [`shared::rpc::JudgeResult::PerCase`]
+pub const CASE_OUTPUT_PREVIEW_BYTES: usize = 1024;
+
+/// Name prefix of the cgroups and temporary directories created while judging
+pub const RESOURCE_PREFIX: &str = "judge-";
+
+/// User id judged programs run as (`nobody`), owning nothing but their work directory
+pub const SANDBOX_UID: u32 = 65534;
+
+/// Group id judged programs run as (`nogroup`)
+pub const SANDBOX_GID: u32 = 65534;
+
+/// Default directory of the read-only case store provisioned into the VM
+pub const DEFAULT_CASE_STORE_DIR: &str = "/var/lib/judge/store";
@@ -1,5 +1,60 @@
-use crate::handler::Handler;
-use shared::rpc::{JudgeRequest, JudgeResponse, JudgeResult};
+use crate::{
+    cache::OutputCache,
+    checker::{CheckerVerdict, PreparedChecker},
+    compare, constants,
+    handler::{
+        CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext, Handler, HandlerError,
+        WorkDirGuard, sandbox,
+    },
+    store::{CaseStore, StoreError},
+    utils,
+};
+use shared::rpc::{
+    self, CaseOutcome, CaseVerdict, CompileStats, JudgeRequest, JudgeResponse, JudgeResult,
+    Language, RuntimeErrorKind, TestCase, TimeLimit,
+};
+use std::{os::unix::process::ExitStatusExt, sync::Arc, time::Duration};
+
+/// Infrastructure failure while judging
+///
+/// Never attributable to the submission, every user-caused outcome is a [`JudgeResult`] instead
+#[derive(Debug, thiserror::Error)]
+pub enum JudgeError {
+    #[error("Handler error: {0}")]
+    Handler(HandlerError),
+    #[error("Case store error: {0}")]
+    Store(#[from] StoreError),
+    #[error("Request has no test cases")]
+    NoTestCases,
+    #[error("No handler registered for {0:?}")]
+    UnsupportedLanguage(Language),
+    #[error("Checker error: {0}")]
+    Checker(String),
+    #[error("Judging did not finish within {0:?}")]
+    Timeout(Duration),
+}
+
+impl JudgeError {
+    /// Whether the agent must stop after reporting this error
+    ///
+    /// Fatal errors may leave the VM in an unknown state, such as leaked cgroups or processes, or
+    /// a broken filesystem. Errors tied to a single request or a transient failure are not.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            JudgeError::Handler(err) => matches!(
+                err,
+                HandlerError::IoError(_)
+                    | HandlerError::InternalError(_)
+                    | HandlerError::CgroupError(_)
+            ),
+            JudgeError::Store(_)
+            | JudgeError::NoTestCases
+            | JudgeError::UnsupportedLanguage(_)
+            | JudgeError::Checker(_)
+            | JudgeError::Timeout(_) => false,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Engine;
@@ -8,116 +63,746 @@ impl Engine {
     pub async fn judge(
         handler: impl Handler,
         request: JudgeRequest,
-        compile_time_limit_ms: u64,
-    ) -> JudgeResponse {
+        compile_limits: CompileLimits,
+        cache: Arc<OutputCache>,
+    ) -> Result<JudgeResponse, JudgeError> {
         let request_id = request.id;
-        let need_compile = handler.needs_compile();
 
-        let ctx = match handler.prepare(&request.source_code).await {
-            Ok(info) => info,
-            Err(err) => {
-                let err: JudgeResult = err.into();
-                return err.into_judge_response(request_id);
-            }
+        let (result, compile_stats) = Self::run(handler, &request, &compile_limits, &cache).await?;
+        let mut response = result.into_judge_response(request_id);
+        response.compile_stats = compile_stats;
+
+        Ok(response)
+    }
+
+    async fn run(
+        handler: impl Handler,
+        request: &JudgeRequest,
+        compile_limits: &CompileLimits,
+        cache: &OutputCache,
+    ) -> Result<(JudgeResult, Option<CompileStats>), JudgeError> {
+        // An empty test set is a misconfigured problem, not a pass
+        if request.test_cases.is_empty() {
+            return Err(JudgeError::NoTestCases);
+        }
+
+        // Both work directories are removed on drop as well, so no early return or cancellation
+        // leaks them
+        let checker = match &request.checker {
+            Some(checker) => Some(PreparedChecker::prepare(checker, request.id).await?),
+            None => None,
         };
 
-        if need_compile {
-            let compile_info = match handler.compile(&ctx, compile_time_limit_ms).await {
-                Ok(info) => info.unwrap(),
-                Err(err) => {
-                    let err: JudgeResult = err.into();
-                    return err.into_judge_response(request_id);
-                }
-            };
+        let mut ctx = match Self::prepare(&handler, request).await {
+            Ok(ctx) => WorkDirGuard::new(ctx),
+            Err(err) => return err.into_verdict(None).map(|verdict| (verdict, None)),
+        };
+        ctx.request_id = Some(request.id);
+        if let Some(seed) = request.seed {
+            ctx.env.push((rpc::SEED_ENV_VAR.into(), seed.to_string()));
+        }
 
-            if !compile_info.status_code.success() {
-                let message = format!(
-                    "Stdout:\n{}\nStderr:\n{}",
-                    compile_info.stdout, compile_info.stderr
-                );
+        let mut runner = CaseRunner {
+            handler: &handler,
+            ctx: &ctx,
+            request,
+            compile_limits,
+            cache,
+            checker: checker.as_ref(),
+            fingerprint: request.fingerprint(),
+            // Compiled lazily, a rejudge fully served by the cache never needs the executable
+            compiled: !handler.needs_compile(),
+            compile_stats: None,
+            store: CaseStore::new(constants::DEFAULT_CASE_STORE_DIR),
+            output_bytes: 0,
+        };
+        let result = Self::run_cases(&mut runner).await;
+        let compile_stats = runner.compile_stats;
 
-                return JudgeResult::CompilationError {
-                    compiler_message: message,
-                }
-                .into_judge_response(request_id);
+        // Clean up after every outcome, the submission's verdict takes precedence over a failure
+        let cleanup = handler.cleanup(&ctx.keep()).await;
+        let checker_cleanup = match checker {
+            Some(checker) => checker.cleanup().await,
+            None => Ok(()),
+        };
+
+        let result = match (result, cleanup, checker_cleanup) {
+            (Ok(_), Err(err), _) => err.into_verdict(None),
+            (Ok(_), _, Err(err)) => Err(err),
+            (result, _, _) => result,
+        };
+
+        result.map(|result| (result, compile_stats))
+    }
+
+    /// Prepare the submission's work directory with all of its files
+    async fn prepare<H: Handler>(
+        handler: &H,
+        request: &JudgeRequest,
+    ) -> Result<ExecutionContext, HandlerError> {
+        let mut ctx = handler.prepare(&request.source_code).await?;
+        if let Err(err) = sandbox::write_files(&mut ctx, &request.files).await {
+            let _ = handler.cleanup(&ctx).await;
+            return Err(err);
+        }
+
+        Ok(ctx)
+    }
+
+    /// Compile the submission, returning the verdict if it does not compile
+    async fn compile<H: Handler>(
+        runner: &mut CaseRunner<'_, H>,
+    ) -> Result<Option<JudgeResult>, JudgeError> {
+        let compile_info = match runner
+            .handler
+            .compile(
+                runner.ctx,
+                &runner.request.compiler_options,
+                runner.compile_limits,
+            )
+            .await
+        {
+            Ok(Some(info)) => info,
+            // A handler bug, the submission was never compiled
+            Ok(None) => {
+                return Err(JudgeError::Handler(HandlerError::InternalError(
+                    "Handler needs a compile step but did not compile",
+                )));
             }
+            Err(err) => return err.into_verdict(None).map(Some),
+        };
+        runner.compile_stats = Some(CompileStats {
+            time_ms: compile_info.compile_time_ms,
+            memory_kib: compile_info.compile_memory_kib,
+        });
+
+        if !compile_info.status_code.success() {
+            let message = format!(
+                "Stdout:\n{}\nStderr:\n{}",
+                compile_info.stdout, compile_info.stderr
+            );
+
+            return Ok(Some(JudgeResult::CompilationError {
+                compiler_message: message,
+                exit_code: compile_info.status_code.code(),
+                signal: compile_info.status_code.signal().map(utils::signal_name),
+            }));
         }
 
+        Ok(None)
+    }
+
+    async fn run_cases<H: Handler>(
+        runner: &mut CaseRunner<'_, H>,
+    ) -> Result<JudgeResult, JudgeError> {
+        let request = runner.request;
         let mut max_cpu_time_ms = 0u64;
         let mut max_real_time_ms = 0u64;
         let mut max_memory_kib = 0u64;
+        let mut max_pids = 0u64;
+        let mut outcomes = Vec::new();
 
-        for case in &request.test_cases {
-            let stdout_limit_bytes = case.expected_output.len() * 2;
-            let stderr_limit_bytes = 128 * 1024;
-            let result = match handler
-                .execute(
-                    &ctx,
-                    &case.input_data,
-                    request.limits.time_ms,
-                    request.limits.memory_kib,
-                    stdout_limit_bytes,
-                    stderr_limit_bytes,
-                )
-                .await
-            {
-                Ok(result) => result,
-                Err(err) => {
-                    let err: JudgeResult = err.into();
-                    return err.into_judge_response(request_id);
+        for (case_index, case) in request.test_cases.iter().enumerate() {
+            let (verdict, info) = match runner.run(case_index, case).await? {
+                CaseRun::Aborted(verdict) => return Ok(verdict),
+                CaseRun::Failed { verdict, .. } if request.stop_on_first_failure => {
+                    return Ok(verdict);
+                }
+                CaseRun::Failed { verdict, info } => (case_verdict(&verdict), info),
+                CaseRun::Passed(info) => {
+                    // Update maximum resource usage
+                    let usage = &info.resource_usage;
+                    max_cpu_time_ms = max_cpu_time_ms.max(usage.cpu_time_ms);
+                    max_real_time_ms = max_real_time_ms.max(usage.real_time_ms);
+                    max_memory_kib = max_memory_kib.max(usage.memory_kib);
+                    max_pids = max_pids.max(usage.max_pids);
+
+                    (CaseVerdict::Accepted, Some(info))
                 }
             };
 
-            // Check time
-            if result.resource_usage.cpu_time_ms > request.limits.time_ms {
-                return JudgeResult::TimeLimitExceeded.into_judge_response(request_id);
-            }
+            outcomes.push(CaseOutcome {
+                case_index,
+                verdict,
+                cpu_time_ms: info
+                    .as_ref()
+                    .map_or(0, |info| info.resource_usage.cpu_time_ms),
+                memory_kib: info
+                    .as_ref()
+                    .map_or(0, |info| info.resource_usage.memory_kib),
+                output: info.map_or_else(String::new, |info| {
+                    truncate(
+                        info.stdout_lossy().into_owned(),
+                        constants::CASE_OUTPUT_PREVIEW_BYTES,
+                    )
+                }),
+            });
+        }
 
-            // Check memory
-            if result.resource_usage.memory_kib > request.limits.memory_kib {
-                return JudgeResult::MemoryLimitExceeded.into_judge_response(request_id);
-            }
+        if !request.stop_on_first_failure {
+            return Ok(JudgeResult::PerCase { cases: outcomes });
+        }
 
-            // Check exit code
-            if !result.status_code.success() {
-                let output_formated =
-                    format!("Stdout:\n{}\nStderr:\n{}", result.stdout, result.stderr);
+        Ok(JudgeResult::Accepted {
+            cpu_time_ms: max_cpu_time_ms,
+            real_time_ms: max_real_time_ms,
+            memory_kib: max_memory_kib,
+            max_pids,
+            cases_run: request.test_cases.len(),
+        })
+    }
+}
+
+/// Outcome of running a single test case
+enum CaseRun {
+    Passed(ExecuteInfo),
+    /// `info` is none if the handler stopped the program
+    Failed {
+        verdict: JudgeResult,
+        info: Option<ExecuteInfo>,
+    },
+    /// Failure of the whole submission, such as a compilation error
+    Aborted(JudgeResult),
+}
+
+/// State carried from one test case to the next
+struct CaseRunner<'a, H> {
+    handler: &'a H,
+    ctx: &'a ExecutionContext,
+    request: &'a JudgeRequest,
+    compile_limits: &'a CompileLimits,
+    cache: &'a OutputCache,
+    checker: Option<&'a PreparedChecker>,
+    fingerprint: [u8; 32],
+    compiled: bool,
+    compile_stats: Option<CompileStats>,
+    store: CaseStore,
+    output_bytes: u64,
+}
+
+impl<H: Handler> CaseRunner<'_, H> {
+    async fn run(&mut self, case_index: usize, case: &TestCase) -> Result<CaseRun, JudgeError> {
+        let request = self.request;
+        let failed = |verdict, info| Ok(CaseRun::Failed { verdict, info });
+        let stopped = |err: HandlerError| match err.into_verdict(Some(case_index)) {
+            Ok(verdict) => failed(verdict, None),
+            Err(err) => Err(err),
+        };
+
+        let input_data = self.store.resolve(&case.input_data).await?;
+        let expected_output = self.store.resolve(&case.expected_output).await?;
+        let mut accepted_outputs = Vec::new();
+        for output in case.accepted_outputs.iter().flatten() {
+            accepted_outputs.push(self.store.resolve(output).await?);
+        }
+        let longest_output = accepted_outputs
+            .iter()
+            .map(|output| output.len())
+            .fold(expected_output.len(), usize::max);
 
-                return JudgeResult::RuntimeError {
-                    actual_output: output_formated,
-                    error_message: "Non-zero exit code".into(),
+        let (time_ms, real_time_ms) = time_limits(request, case);
+        let memory_kib = case
+            .memory_kib_override
+            .unwrap_or(request.limits.memory_kib);
+        // Never buffer more than what is left of the aggregate output budget
+        let remaining_bytes = request
+            .limits
+            .output_budget_bytes
+            .map_or(usize::MAX, |budget| {
+                budget.saturating_sub(self.output_bytes) as usize
+            });
+        let limits = ExecuteLimits {
+            time_ms,
+            real_time_ms,
+            memory_kib,
+            stack_kib: request.limits.stack_limit_kib.unwrap_or(memory_kib),
+            stdout_bytes: (longest_output * 2).min(remaining_bytes),
+            stderr_bytes: (128 * 1024).min(remaining_bytes),
+            disk_kib: request.limits.disk_limit_kib,
+            pids: request
+                .limits
+                .pid_limit
+                .unwrap_or(constants::DEFAULT_PID_LIMIT),
+        };
+        let result = match self.cache.replay(self.fingerprint, case_index, &limits) {
+            Some(Ok(result)) => result,
+            Some(Err(err)) => return stopped(err),
+            None => {
+                if !self.compiled {
+                    if let Some(verdict) = Engine::compile(self).await? {
+                        return Ok(CaseRun::Aborted(verdict));
+                    }
+                    self.compiled = true;
                 }
-                .into_judge_response(request_id);
+
+                let result = match self.handler.execute(self.ctx, &input_data, &limits).await {
+                    Ok(result) => result,
+                    Err(err) => return stopped(err),
+                };
+                self.cache
+                    .insert(self.fingerprint, case_index, &limits, &result);
+                result
             }
+        };
+
+        // Check aggregate output
+        self.output_bytes += (result.stdout.len() + result.stderr.len()) as u64;
+        if let Some(budget) = request.limits.output_budget_bytes
+            && self.output_bytes > budget
+        {
+            let verdict = JudgeResult::OutputLimitExceeded {
+                case_index: Some(case_index),
+            };
+            return failed(verdict, Some(result));
+        }
+
+        // Check time
+        if result.resource_usage.cpu_time_ms > limits.time_ms {
+            let verdict = JudgeResult::TimeLimitExceeded {
+                case_index: Some(case_index),
+                limit: TimeLimit::CpuTime,
+            };
+            return failed(verdict, Some(result));
+        }
 
-            // Check output
-            let expected = case.expected_output.trim();
-            let actual = result.stdout.trim();
+        // Check memory
+        if result.resource_usage.memory_kib > memory_kib {
+            let verdict = JudgeResult::MemoryLimitExceeded {
+                case_index: Some(case_index),
+            };
+            return failed(verdict, Some(result));
+        }
 
-            if expected != actual {
-                return JudgeResult::WrongAnswer {
-                    expected_output: expected.to_string(),
-                    actual_output: actual.to_string(),
+        // Check exit code
+        if !result.status_code.success() {
+            let kind = match result.signal {
+                // Deep recursion hits the stack limit long before the memory limit
+                Some(libc::SIGSEGV) => RuntimeErrorKind::SegmentationFault,
+                Some(_) => RuntimeErrorKind::Signaled,
+                None => RuntimeErrorKind::NonZeroExit,
+            };
+            let error_message = match (result.signal, result.status_code.code()) {
+                (Some(libc::SIGSEGV), _) => format!(
+                    "Killed by signal {} ({}), possibly a stack overflow (stack limit {} KiB)",
+                    utils::signal_name(libc::SIGSEGV),
+                    libc::SIGSEGV,
+                    limits.stack_kib
+                ),
+                (Some(signal), _) => {
+                    format!("Killed by signal {} ({signal})", utils::signal_name(signal))
                 }
-                .into_judge_response(request_id);
-            }
+                (None, Some(code)) => format!("Exited with code {code}"),
+                (None, None) => "Exited abnormally".into(),
+            };
 
-            // Update maximum resource usage
-            max_cpu_time_ms = max_cpu_time_ms.max(result.resource_usage.cpu_time_ms);
-            max_real_time_ms = max_real_time_ms.max(result.resource_usage.real_time_ms);
-            max_memory_kib = max_memory_kib.max(result.resource_usage.memory_kib);
+            let verdict = JudgeResult::RuntimeError {
+                case_index,
+                stdout: result.stdout_lossy().into_owned(),
+                stderr: result.stderr.clone(),
+                exit_code: result.status_code.code(),
+                signal: result.signal.map(utils::signal_name),
+                kind,
+                error_message,
+            };
+            return failed(verdict, Some(result));
         }
 
-        if let Err(e) = handler.cleanup(&ctx).await {
-            let e: JudgeResult = e.into();
-            return e.into_judge_response(request_id);
+        // Check output
+        if let Some(checker) = self.checker {
+            return match checker
+                .check(&input_data, &expected_output, &result.stdout)
+                .await?
+            {
+                CheckerVerdict::Accepted => Ok(CaseRun::Passed(result)),
+                CheckerVerdict::PresentationError => {
+                    let verdict = JudgeResult::PresentationError { case_index };
+                    failed(verdict, Some(result))
+                }
+                CheckerVerdict::Rejected { message } => {
+                    let verdict = JudgeResult::WrongAnswer {
+                        case_index,
+                        expected_output: expected_output.to_string(),
+                        actual_output: result.stdout_lossy().into_owned(),
+                        hint: message,
+                    };
+                    failed(verdict, Some(result))
+                }
+            };
         }
 
-        JudgeResult::Accepted {
-            cpu_time_ms: max_cpu_time_ms,
-            real_time_ms: max_real_time_ms,
-            memory_kib: max_memory_kib,
+        if let Err(mismatch) = compare::stdout_matches_any(
+            &expected_output,
+            &accepted_outputs,
+            &result.stdout,
+            &request.compare,
+        ) {
+            let stdout = result.stdout_lossy();
+
+            // Right answer, wrong layout
+            let same_tokens = std::iter::once(&expected_output)
+                .chain(&accepted_outputs)
+                .any(|output| compare::same_tokens(output, &stdout, &request.compare));
+            if same_tokens {
+                let verdict = JudgeResult::PresentationError { case_index };
+                return failed(verdict, Some(result));
+            }
+
+            // A misdirected answer is more actionable than where the outputs differ
+            let hint = if request.compare.stderr_hint
+                && compare::stderr_matches(&expected_output, &result.stderr, &request.compare)
+            {
+                Some("Expected output was found on stderr, answers must go to stdout".into())
+            } else {
+                mismatch.hint
+            };
+
+            let verdict = JudgeResult::WrongAnswer {
+                case_index,
+                expected_output: compare::normalize(&expected_output, request.compare.mode)
+                    .into_owned(),
+                actual_output: compare::normalize(&stdout, request.compare.mode).into_owned(),
+                hint,
+            };
+            return failed(verdict, Some(result));
         }
-        .into_judge_response(request_id)
+
+        Ok(CaseRun::Passed(result))
+    }
+}
+
+/// Cpu and wall-clock time limits of a test case in milliseconds
+pub fn time_limits(request: &JudgeRequest, case: &TestCase) -> (u64, u64) {
+    let time_ms = case.time_ms_override.unwrap_or(request.limits.time_ms);
+    let real_time_ms = request
+        .limits
+        .real_time_ms
+        .unwrap_or(time_ms.saturating_mul(constants::DEFAULT_REAL_TIME_FACTOR));
+
+    (time_ms, real_time_ms)
+}
+
+/// Verdict of a test case failure for [`JudgeResult::PerCase`]
+fn case_verdict(verdict: &JudgeResult) -> CaseVerdict {
+    match verdict {
+        JudgeResult::Accepted { .. } => CaseVerdict::Accepted,
+        JudgeResult::WrongAnswer { .. } => CaseVerdict::WrongAnswer,
+        JudgeResult::RuntimeError { .. } => CaseVerdict::RuntimeError,
+        JudgeResult::TimeLimitExceeded { .. } => CaseVerdict::TimeLimitExceeded,
+        JudgeResult::MemoryLimitExceeded { .. } => CaseVerdict::MemoryLimitExceeded,
+        JudgeResult::OutputLimitExceeded { .. } => CaseVerdict::OutputLimitExceeded,
+        JudgeResult::DiskLimitExceeded { .. } => CaseVerdict::DiskLimitExceeded,
+        JudgeResult::PidLimitExceeded { .. } => CaseVerdict::PidLimitExceeded,
+        JudgeResult::PresentationError { .. } => CaseVerdict::PresentationError,
+        JudgeResult::CompilationError { .. }
+        | JudgeResult::InternalError { .. }
+        | JudgeResult::PerCase { .. } => unreachable!("not a test case verdict"),
+    }
+}
+
+/// Cut `output` to at most `max_bytes`, on a character boundary
+fn truncate(mut output: String, max_bytes: usize) -> String {
+    output.truncate(output.floor_char_boundary(max_bytes));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{EchoHandler, request};
+    use shared::rpc::CompareMode;
+
+    async fn judge(handler: EchoHandler, request: JudgeRequest) -> JudgeResult {
+        Engine::judge(handler, request, CompileLimits::default(), Arc::default())
+            .await
+            .unwrap()
+            .result
+    }
+
+    /// Request stopping on the first failure, so its verdict is the failure itself
+    fn stopping_request(cases: &[(&str, &str)]) -> JudgeRequest {
+        let mut request = request(Language::Python, cases);
+        request.stop_on_first_failure = true;
+        request
+    }
+
+    #[tokio::test]
+    async fn requests_without_cases_are_an_error() {
+        let request = request(Language::Python, &[]);
+        let err = Engine::judge(
+            EchoHandler::default(),
+            request,
+            CompileLimits::default(),
+            Arc::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err, JudgeError::NoTestCases));
+        assert!(!err.is_fatal());
+    }
+
+    #[tokio::test]
+    async fn handlers_skipping_their_compile_step_are_an_error() {
+        let handler = EchoHandler {
+            needs_compile: true,
+            ..EchoHandler::default()
+        };
+        let err = Engine::judge(
+            handler,
+            stopping_request(&[("", "")]),
+            CompileLimits::default(),
+            Arc::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            JudgeError::Handler(HandlerError::InternalError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_the_cases_run_and_the_failing_case() {
+        let passing = stopping_request(&[("1", "1"), ("2", "2"), ("3", "3")]);
+        let failing = stopping_request(&[("1", "1"), ("2", "3"), ("3", "3")]);
+
+        assert!(matches!(
+            judge(EchoHandler::default(), passing).await,
+            JudgeResult::Accepted { cases_run: 3, .. }
+        ));
+        assert!(matches!(
+            judge(EchoHandler::default(), failing).await,
+            JudgeResult::WrongAnswer { case_index: 1, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn segmentation_faults_mention_the_stack_limit() {
+        let crashing = EchoHandler {
+            wait_status: libc::SIGSEGV,
+            ..EchoHandler::default()
+        };
+        let mut request = stopping_request(&[("", "")]);
+        request.limits.stack_limit_kib = Some(8 * 1024);
+
+        let JudgeResult::RuntimeError {
+            kind,
+            error_message,
+            signal,
+            ..
+        } = judge(crashing, request).await
+        else {
+            panic!("Expected a runtime error");
+        };
+        assert_eq!(kind, RuntimeErrorKind::SegmentationFault);
+        assert_eq!(signal.as_deref(), Some("SIGSEGV"));
+        assert_eq!(
+            error_message,
+            "Killed by signal SIGSEGV (11), possibly a stack overflow (stack limit 8192 KiB)"
+        );
+    }
+
+    #[tokio::test]
+    async fn stack_limit_defaults_to_the_memory_limit() {
+        let crashing = EchoHandler {
+            wait_status: libc::SIGSEGV,
+            ..EchoHandler::default()
+        };
+        let request = stopping_request(&[("", "")]);
+
+        let JudgeResult::RuntimeError { error_message, .. } = judge(crashing, request).await else {
+            panic!("Expected a runtime error");
+        };
+        assert!(error_message.ends_with("(stack limit 65536 KiB)"));
+    }
+
+    #[tokio::test]
+    async fn rejudges_with_more_memory_rerun_stack_overflows() {
+        let cache = Arc::new(OutputCache::default());
+        let crashing = EchoHandler {
+            wait_status: libc::SIGSEGV,
+            ..EchoHandler::default()
+        };
+        let limits = CompileLimits::default();
+        let request = stopping_request(&[("", "")]);
+        Engine::judge(crashing, request, limits, cache.clone())
+            .await
+            .unwrap();
+
+        // The default stack grows with the memory limit, the recorded crash no longer applies
+        let mut request = stopping_request(&[("", "")]);
+        request.limits.memory_kib *= 2;
+        let response = Engine::judge(EchoHandler::default(), request, limits, cache)
+            .await
+            .unwrap();
+        assert!(matches!(response.result, JudgeResult::Accepted { .. }));
+    }
+
+    #[tokio::test]
+    async fn runtime_errors_keep_stdout_stderr_and_exit_code_apart() {
+        let failing = EchoHandler {
+            wait_status: 3 << 8,
+            stderr: "oops".into(),
+            ..EchoHandler::default()
+        };
+        let request = stopping_request(&[("partial", "full")]);
+
+        let JudgeResult::RuntimeError {
+            stdout,
+            stderr,
+            exit_code,
+            signal,
+            kind,
+            error_message,
+            ..
+        } = judge(failing, request).await
+        else {
+            panic!("Expected a runtime error");
+        };
+        assert_eq!((stdout.as_str(), stderr.as_str()), ("partial", "oops"));
+        assert_eq!((exit_code, signal), (Some(3), None));
+        assert_eq!(kind, RuntimeErrorKind::NonZeroExit);
+        assert_eq!(error_message, "Exited with code 3");
+    }
+
+    #[tokio::test]
+    async fn other_signals_are_named() {
+        let aborting = EchoHandler {
+            wait_status: libc::SIGABRT,
+            ..EchoHandler::default()
+        };
+
+        let JudgeResult::RuntimeError {
+            kind,
+            error_message,
+            ..
+        } = judge(aborting, stopping_request(&[("", "")])).await
+        else {
+            panic!("Expected a runtime error");
+        };
+        assert_eq!(kind, RuntimeErrorKind::Signaled);
+        assert_eq!(error_message, "Killed by signal SIGABRT (6)");
+    }
+
+    #[tokio::test]
+    async fn case_memory_overrides_apply_to_their_case_only() {
+        let handler = EchoHandler {
+            memory_kib: 2048,
+            ..EchoHandler::default()
+        };
+        let mut request = request(Language::Python, &[("1", "1"), ("2", "2"), ("3", "3")]);
+        request.limits.memory_kib = 4096;
+        request.test_cases[1].memory_kib_override = Some(1024);
+        request.test_cases[2].memory_kib_override = Some(8192);
+
+        let JudgeResult::PerCase { cases } = judge(handler, request).await else {
+            panic!("Expected per case results");
+        };
+        let verdicts: Vec<_> = cases.into_iter().map(|case| case.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                CaseVerdict::Accepted,
+                CaseVerdict::MemoryLimitExceeded,
+                CaseVerdict::Accepted
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_peak_processes_within_the_pid_limit() {
+        let forking = EchoHandler {
+            pids: constants::DEFAULT_PID_LIMIT,
+            ..EchoHandler::default()
+        };
+
+        assert!(matches!(
+            judge(forking.clone(), stopping_request(&[("", "")])).await,
+            JudgeResult::Accepted { max_pids, .. } if max_pids == constants::DEFAULT_PID_LIMIT
+        ));
+
+        let mut request = stopping_request(&[("", "")]);
+        request.limits.pid_limit = Some(constants::DEFAULT_PID_LIMIT - 1);
+        assert!(matches!(
+            judge(forking, request).await,
+            JudgeResult::PidLimitExceeded {
+                case_index: Some(0)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn passes_the_seed_to_the_program() {
+        let handler = EchoHandler {
+            print_env: true,
+            ..EchoHandler::default()
+        };
+        let mut seeded = stopping_request(&[("", "JUDGE_SEED=7")]);
+        seeded.seed = Some(7);
+        let unseeded = stopping_request(&[("", "JUDGE_SEED=7")]);
+
+        assert!(matches!(
+            judge(handler.clone(), seeded).await,
+            JudgeResult::Accepted { .. }
+        ));
+        assert!(matches!(
+            judge(handler, unseeded).await,
+            JudgeResult::WrongAnswer { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn output_budget_spans_all_cases() {
+        let handler = EchoHandler {
+            stderr: "!".into(),
+            ..EchoHandler::default()
+        };
+        // Every case writes 4 bytes, the third one crosses the budget
+        let mut request = stopping_request(&[("abc", "abc"); 3]);
+        request.limits.output_budget_bytes = Some(10);
+
+        assert!(matches!(
+            judge(handler, request).await,
+            JudgeResult::OutputLimitExceeded {
+                case_index: Some(2)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn hints_at_answers_written_to_stderr() {
+        let misdirected = EchoHandler {
+            stderr: "42\n".into(),
+            ..EchoHandler::default()
+        };
+        let mut request = stopping_request(&[("", "42")]);
+        request.compare.stderr_hint = true;
+
+        let JudgeResult::WrongAnswer { hint, .. } = judge(misdirected.clone(), request).await
+        else {
+            panic!("Expected a wrong answer");
+        };
+        assert_eq!(
+            hint.as_deref(),
+            Some("Expected output was found on stderr, answers must go to stdout")
+        );
+
+        // Compared trimmed, even where the case compares exact bytes
+        let mut exact = stopping_request(&[("", "42")]);
+        exact.compare.stderr_hint = true;
+        exact.compare.mode = CompareMode::Exact;
+        let JudgeResult::WrongAnswer { hint, .. } = judge(misdirected.clone(), exact).await else {
+            panic!("Expected a wrong answer");
+        };
+        assert!(hint.is_some());
+
+        // Off unless asked for
+        let JudgeResult::WrongAnswer { hint, .. } =
+            judge(misdirected, stopping_request(&[("", "42")])).await
+        else {
+            panic!("Expected a wrong answer");
+        };
+        assert_eq!(hint, None);
     }
 }
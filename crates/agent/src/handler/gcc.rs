@@ -0,0 +1,133 @@
+use crate::{
+    constants,
+    handler::{
+        CompileInfo, CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext, Handler,
+        HandlerError, sandbox,
+    },
+    seccomp::SeccompProfile,
+};
+use shared::rpc::CompilerOptions;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Language of the GCC family, all that differs between [`GccHandler`]s
+#[derive(Debug, Clone, Copy)]
+pub struct GccLanguage {
+    /// Compiler driver, e.g. `g++`
+    pub compiler: &'static str,
+    /// Names the cgroups of the compiler and the program
+    pub name: &'static str,
+    /// File name the source is written to
+    pub source_name: &'static str,
+    /// Arguments passed before [`CompilerOptions::extra_flags`]
+    pub args: &'static [&'static str],
+    /// Extensions of further files compiled along with the source
+    pub unit_extensions: &'static [&'static str],
+}
+
+impl GccLanguage {
+    pub const C: Self = Self {
+        compiler: "gcc",
+        name: "c",
+        source_name: "input.c",
+        args: &["-std=c11", "-static"],
+        unit_extensions: &["c"],
+    };
+
+    pub const CPP: Self = Self {
+        compiler: "g++",
+        name: "cpp",
+        source_name: "input.cpp",
+        args: &["-static"],
+        unit_extensions: &["cpp", "cc", "cxx"],
+    };
+}
+
+/// Compiles a [`GccLanguage`] into a static executable and runs it
+#[derive(Debug, Clone, Copy)]
+pub struct GccHandler {
+    /// Language compiled, e.g. [`GccLanguage::CPP`]
+    pub language: GccLanguage,
+
+    /// Run the compiler under [`SeccompProfile::Compile`], inside the work directory
+    ///
+    /// Only disable in trusted environments
+    pub sandbox_compile: bool,
+
+    /// Seccomp profile of the program, [`SeccompProfile::Strict`] by default
+    ///
+    /// Programs are linked statically, so they need nothing from the file system to start.
+    pub seccomp_profile: SeccompProfile,
+
+    /// Interval between memory usage samples while the program runs
+    pub memory_sample_interval: Duration,
+}
+
+impl GccHandler {
+    pub fn new(language: GccLanguage) -> Self {
+        Self {
+            language,
+            sandbox_compile: true,
+            seccomp_profile: SeccompProfile::Strict,
+            memory_sample_interval: Duration::from_millis(
+                constants::DEFAULT_MEMORY_SAMPLE_INTERVAL_MS,
+            ),
+        }
+    }
+}
+
+impl Handler for GccHandler {
+    fn needs_compile(&self) -> bool {
+        true
+    }
+
+    fn seccomp_profile(&self) -> SeccompProfile {
+        self.seccomp_profile
+    }
+
+    async fn prepare(&self, source_code: &str) -> Result<ExecutionContext, HandlerError> {
+        sandbox::prepare(source_code, self.language.source_name).await
+    }
+
+    async fn compile(
+        &self,
+        context: &ExecutionContext,
+        options: &CompilerOptions,
+        limits: &CompileLimits,
+    ) -> Result<Option<CompileInfo>, HandlerError> {
+        let command = sandbox::gcc_command(
+            self.language.compiler,
+            context,
+            options,
+            self.language.args,
+            self.language.unit_extensions,
+            self.sandbox_compile,
+        )?;
+
+        sandbox::compile(command, self.language.name, context, limits)
+            .await
+            .map(Some)
+    }
+
+    async fn execute(
+        &self,
+        context: &ExecutionContext,
+        input_data: &str,
+        limits: &ExecuteLimits,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        sandbox::execute(
+            Command::new(&context.executable_file),
+            self.language.name,
+            context,
+            input_data,
+            limits,
+            self.memory_sample_interval,
+            self.seccomp_profile(),
+        )
+        .await
+    }
+
+    async fn cleanup(&self, context: &ExecutionContext) -> Result<(), HandlerError> {
+        sandbox::cleanup(context).await
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    constants,
+    handler::{
+        CompileInfo, CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext, Handler,
+        HandlerError, sandbox,
+    },
+    seccomp::{SeccompFilter, SeccompProfile},
+};
+use shared::rpc::CompilerOptions;
+use std::{path::Path, time::Duration};
+use tokio::process::Command;
+
+const MAX_THREAD_STACK_KIB: u64 = 1024 * 1024 - 1;
+
+/// Compiles `Main.java` with `javac` and runs class `Main` with `java`
+///
+/// Classes are compiled into [`ExecutionContext::class_path`] and run from there. Both steps run
+/// under [`SeccompFilter::apply_jvm_filter`]. The JVM gets a heap as large as the memory limit,
+/// and its cgroup [`JavaHandler::runtime_memory_kib`] more for the rest of the JVM.
+/// [`CompilerOptions::extra_flags`] are meant for GCC and ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct JavaHandler {
+    /// Run the compiler under [`SeccompFilter::apply_jvm_filter`], inside the work directory
+    ///
+    /// Only disable in trusted environments
+    pub sandbox_compile: bool,
+
+    /// Threads the JVM starts on its own (garbage collector, JIT compilers, ...), allowed on top
+    /// of [`ExecuteLimits::pids`]
+    pub runtime_threads: u64,
+
+    /// Memory the JVM uses besides the heap, allowed on top of [`ExecuteLimits::memory_kib`]
+    ///
+    /// Reported memory usage is net of it, so a Java program is measured by what its heap needs,
+    /// like a native program.
+    pub runtime_memory_kib: u64,
+
+    /// Interval between memory usage samples while the program runs
+    pub memory_sample_interval: Duration,
+}
+
+impl Default for JavaHandler {
+    fn default() -> Self {
+        Self {
+            sandbox_compile: true,
+            runtime_threads: constants::DEFAULT_JVM_RUNTIME_THREADS,
+            runtime_memory_kib: constants::DEFAULT_JVM_RUNTIME_MEMORY_KIB,
+            memory_sample_interval: Duration::from_millis(
+                constants::DEFAULT_MEMORY_SAMPLE_INTERVAL_MS,
+            ),
+        }
+    }
+}
+
+impl Handler for JavaHandler {
+    fn needs_compile(&self) -> bool {
+        true
+    }
+
+    fn seccomp_profile(&self) -> SeccompProfile {
+        SeccompProfile::Jvm
+    }
+
+    async fn prepare(&self, source_code: &str) -> Result<ExecutionContext, HandlerError> {
+        let mut context = sandbox::prepare(source_code, "Main.java").await?;
+        context.class_path = Some(context.work_dir.join("classes"));
+        Ok(context)
+    }
+
+    async fn compile(
+        &self,
+        context: &ExecutionContext,
+        options: &CompilerOptions,
+        limits: &CompileLimits,
+    ) -> Result<Option<CompileInfo>, HandlerError> {
+        // Leaving the rest of the memory limit to the JVM itself
+        let mut command = Command::new("javac");
+        command
+            .arg(format!("-J-Xmx{}k", limits.memory_kib / 2))
+            .arg("-J-XX:+UseSerialGC")
+            .args(["-encoding", "UTF-8", "-nowarn"]);
+        if options.debug_info {
+            command.arg("-g");
+        }
+        command
+            .arg("-d")
+            .arg(class_path(context)?)
+            .arg(&context.source_file)
+            .args(context.extra_files.iter().filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "java")
+            }));
+
+        if self.sandbox_compile {
+            unsafe {
+                command
+                    .current_dir(&context.work_dir)
+                    .env("TMPDIR", &context.work_dir)
+                    .pre_exec(SeccompFilter::apply_jvm_filter);
+            }
+        }
+
+        sandbox::compile(command, "java", context, limits)
+            .await
+            .map(Some)
+    }
+
+    async fn execute(
+        &self,
+        context: &ExecutionContext,
+        input_data: &str,
+        limits: &ExecuteLimits,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        // The JVM rejects thread stacks of 1GB and more
+        let stack_kib = limits.stack_kib.min(MAX_THREAD_STACK_KIB);
+        let mut command = Command::new("java");
+        command
+            .arg(format!("-Xmx{}k", limits.memory_kib))
+            .arg(format!("-Xss{stack_kib}k"))
+            .arg("-XX:+UseSerialGC")
+            // Shared memory files in /tmp would be visible to every other judged program
+            .arg("-XX:-UsePerfData")
+            .arg("-cp")
+            .arg(class_path(context)?)
+            .arg("Main");
+
+        let limits = ExecuteLimits {
+            memory_kib: limits.memory_kib + self.runtime_memory_kib,
+            pids: limits.pids + self.runtime_threads,
+            ..*limits
+        };
+
+        let mut info = sandbox::execute(
+            command,
+            "java",
+            context,
+            input_data,
+            &limits,
+            self.memory_sample_interval,
+            self.seccomp_profile(),
+        )
+        .await?;
+        let usage = &mut info.resource_usage;
+        usage.memory_kib = usage.memory_kib.saturating_sub(self.runtime_memory_kib);
+
+        Ok(info)
+    }
+
+    async fn cleanup(&self, context: &ExecutionContext) -> Result<(), HandlerError> {
+        sandbox::cleanup(context).await
+    }
+}
+
+/// Directory of the compiled classes, set up by [`JavaHandler::prepare`]
+fn class_path(context: &ExecutionContext) -> Result<&Path, HandlerError> {
+    context
+        .class_path
+        .as_deref()
+        .ok_or(HandlerError::InternalError("Context has no class path"))
+}
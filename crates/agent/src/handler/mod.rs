@@ -1,45 +1,350 @@
-mod cpp;
-pub use cpp::CppHandler;
+mod gcc;
+mod java;
+mod python;
+pub mod sandbox;
+pub use gcc::{GccHandler, GccLanguage};
+pub use java::JavaHandler;
+pub use python::PythonHandler;
 
-use shared::rpc::JudgeResult;
-use std::{path::PathBuf, process::ExitStatus};
+use crate::{constants, engine::JudgeError, seccomp::SeccompProfile};
+use cgroups_rs::{
+    CgroupPid,
+    fs::{Cgroup, hierarchies, pid::PidController},
+};
+use shared::rpc::{CompilerOptions, JudgeResult, TimeLimit};
+use std::{
+    borrow::Cow,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Child,
+    time::{MissedTickBehavior, interval, timeout},
+};
+use tokio_retry::{
+    Retry,
+    strategy::{ExponentialBackoff, jitter},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum HandlerError {
     #[error("Io error: {0}")]
     IoError(#[from] std::io::Error),
-    #[error("Time limit exceeded")]
+    #[error("Real time limit exceeded")]
     TimeLimitExceeded,
     #[error("Memory limit exceeded")]
     MemoryLimitExceeded,
     #[error("Output limit exceeded")]
     OutputLimitExceeded,
+    #[error("Disk limit exceeded")]
+    DiskLimitExceeded,
+    #[error("Pid limit exceeded")]
+    PidLimitExceeded,
     #[error("Internal error: {0}")]
     InternalError(&'static str),
+    #[error("Compiler crashed: {0}")]
+    CompilerCrashed(String),
+    #[error("Compiler flag not allowed: \"{0}\"")]
+    ForbiddenCompilerFlag(String),
+    #[error("Invalid source file name: \"{0}\"")]
+    InvalidFileName(String),
     #[error("Cgroup error: {0}")]
     CgroupError(#[from] cgroups_rs::fs::error::Error),
+    #[error("Cannot add task to cgroup: {0}")]
+    AttachCgroupError(cgroups_rs::fs::error::Error),
     #[error("Parse cpu stats error: {0}")]
     ParseCpuStatsError(#[from] crate::utils::ParseCpuStatsError),
 }
 
-impl From<HandlerError> for JudgeResult {
-    fn from(value: HandlerError) -> Self {
-        match value {
-            HandlerError::IoError(err) => JudgeResult::InternalError {
-                error_message: err.to_string(),
-            },
-            HandlerError::TimeLimitExceeded => JudgeResult::TimeLimitExceeded,
-            HandlerError::MemoryLimitExceeded => JudgeResult::MemoryLimitExceeded,
-            HandlerError::OutputLimitExceeded => JudgeResult::OutputLimitExceeded,
-            HandlerError::InternalError(err) => JudgeResult::InternalError {
-                error_message: err.into(),
-            },
-            HandlerError::CgroupError(e) => JudgeResult::InternalError {
-                error_message: e.to_string(),
-            },
-            HandlerError::ParseCpuStatsError(e) => JudgeResult::InternalError {
-                error_message: e.to_string(),
-            },
+impl HandlerError {
+    /// Split into a verdict attributable to the submission or an infrastructure failure
+    ///
+    /// `case_index` is the test case being executed, if any
+    pub fn into_verdict(self, case_index: Option<usize>) -> Result<JudgeResult, JudgeError> {
+        match self {
+            HandlerError::TimeLimitExceeded => Ok(JudgeResult::TimeLimitExceeded {
+                case_index,
+                limit: TimeLimit::RealTime,
+            }),
+            HandlerError::MemoryLimitExceeded => {
+                Ok(JudgeResult::MemoryLimitExceeded { case_index })
+            }
+            HandlerError::OutputLimitExceeded => {
+                Ok(JudgeResult::OutputLimitExceeded { case_index })
+            }
+            HandlerError::DiskLimitExceeded => Ok(JudgeResult::DiskLimitExceeded { case_index }),
+            HandlerError::PidLimitExceeded => Ok(JudgeResult::PidLimitExceeded { case_index }),
+            HandlerError::IoError(_)
+            | HandlerError::InternalError(_)
+            | HandlerError::CompilerCrashed(_)
+            | HandlerError::ForbiddenCompilerFlag(_)
+            | HandlerError::InvalidFileName(_)
+            | HandlerError::CgroupError(_)
+            | HandlerError::AttachCgroupError(_)
+            | HandlerError::ParseCpuStatsError(_) => Err(JudgeError::Handler(self)),
+        }
+    }
+}
+
+/// Cgroup deleted when dropped, so no early return or panic leaks it
+///
+/// Whatever is left inside, such as descendants outliving the judged program, is killed before
+/// every deletion. Dropping ignores failures, call [`CgroupGuard::delete`] where a failed
+/// deletion should be reported.
+#[derive(Debug)]
+pub struct CgroupGuard(Option<Cgroup>);
+
+impl CgroupGuard {
+    pub fn new(cg: Cgroup) -> Self {
+        Self(Some(cg))
+    }
+
+    /// Kill what is left inside and delete the cgroup now, reporting failures
+    ///
+    /// Retries briefly, killed processes only leave the cgroup once they have exited.
+    pub async fn delete(mut self) -> Result<(), HandlerError> {
+        let cg = self.0.take().expect("cgroup is only taken once");
+        kill_tasks(&cg);
+
+        let retry_strategy = ExponentialBackoff::from_millis(2)
+            .factor(5)
+            .map(jitter)
+            .take(4);
+        Retry::spawn(retry_strategy, || async { cg.delete() }).await?;
+        Ok(())
+    }
+}
+
+impl Deref for CgroupGuard {
+    type Target = Cgroup;
+
+    fn deref(&self) -> &Cgroup {
+        self.0.as_ref().expect("cgroup is only taken once")
+    }
+}
+
+impl Drop for CgroupGuard {
+    fn drop(&mut self) {
+        if let Some(cg) = self.0.take() {
+            kill_tasks(&cg);
+            let _ = cg.delete();
+        }
+    }
+}
+
+/// Work directory removed when dropped, unless [`WorkDirGuard::keep`] takes the context back
+///
+/// Covers early returns, panics and cancellation, such as a request running past its deadline.
+/// Removal is best effort, call the handler's [`Handler::cleanup`] on the kept context where a
+/// failure should be reported.
+#[derive(Debug)]
+pub struct WorkDirGuard(Option<ExecutionContext>);
+
+impl WorkDirGuard {
+    pub fn new(context: ExecutionContext) -> Self {
+        Self(Some(context))
+    }
+
+    /// Take the context back, leaving its work directory in place
+    pub fn keep(mut self) -> ExecutionContext {
+        self.0.take().expect("context is only taken once")
+    }
+}
+
+impl Deref for WorkDirGuard {
+    type Target = ExecutionContext;
+
+    fn deref(&self) -> &ExecutionContext {
+        self.0.as_ref().expect("context is only taken once")
+    }
+}
+
+impl DerefMut for WorkDirGuard {
+    fn deref_mut(&mut self) -> &mut ExecutionContext {
+        self.0.as_mut().expect("context is only taken once")
+    }
+}
+
+impl Drop for WorkDirGuard {
+    fn drop(&mut self) {
+        if let Some(context) = self.0.take() {
+            let _ = std::fs::remove_dir_all(&context.work_dir);
+        }
+    }
+}
+
+/// Kill every process in a cgroup, best effort
+///
+/// `cgroup.kill` needs cgroup v2 on Linux 5.14+, elsewhere the processes are signalled one by one.
+fn kill_tasks(cg: &Cgroup) {
+    if cg.kill().is_ok() {
+        return;
+    }
+
+    for pid in cg.procs() {
+        unsafe {
+            libc::kill(pid.pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+/// Add a just-spawned process to a cgroup
+///
+/// Retries briefly, the pid may not be visible yet or controller delegation may still lag behind.
+pub async fn add_task_with_retry(cg: &Cgroup, pid: u32) -> Result<(), HandlerError> {
+    retry_add(|| cg.add_task(CgroupPid::from(pid as u64)))
+        .await
+        .map_err(HandlerError::AttachCgroupError)
+}
+
+/// Run `add` until it succeeds, up to 4 retries with a short backoff
+async fn retry_add<E>(mut add: impl FnMut() -> Result<(), E>) -> Result<(), E> {
+    let retry_strategy = ExponentialBackoff::from_millis(2)
+        .factor(5)
+        .map(jitter)
+        .take(4);
+
+    Retry::spawn(retry_strategy, || std::future::ready(add())).await
+}
+
+/// Best-effort removal of judging cgroups and temporary directories left behind
+///
+/// Only call it while no judgement is running, anything named with
+/// [`constants::RESOURCE_PREFIX`] is killed and removed.
+pub fn sweep_resources() {
+    for name in judging_cgroups() {
+        let cg = Cgroup::load(hierarchies::auto(), &name);
+        kill_tasks(&cg);
+        let _ = cg.delete();
+    }
+
+    let temp_dir = std::env::temp_dir();
+    for name in prefixed_entries(&temp_dir) {
+        let _ = std::fs::remove_dir_all(temp_dir.join(name));
+    }
+}
+
+/// Names of the judging cgroups currently present
+fn judging_cgroups() -> Vec<String> {
+    let hier = hierarchies::auto();
+    // On v1 every judging cgroup has a memory controller, so that hierarchy lists them all
+    let cgroup_root = if hier.v2() {
+        hier.root()
+    } else {
+        hier.root().join("memory")
+    };
+
+    prefixed_entries(&cgroup_root)
+}
+
+/// Names of the entries of `dir` starting with [`constants::RESOURCE_PREFIX`]
+fn prefixed_entries(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(constants::RESOURCE_PREFIX))
+        .collect()
+}
+
+/// Wait for a child with piped stdout/stderr to exit and collect its output
+///
+/// If `time_limit` elapses or an output grows past its limit, the child is killed and reaped
+/// before returning, so it cannot outlive the judgement. Output is counted while it is read and
+/// never buffered beyond the limit. Spawn it with `kill_on_drop` as well, to cover cancellation.
+pub async fn wait_with_timeout(
+    mut child: Child,
+    time_limit: Duration,
+    stdout_limit: usize,
+    stderr_limit: usize,
+) -> Result<Output, HandlerError> {
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or(HandlerError::InternalError("Child stdout is not piped"))?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or(HandlerError::InternalError("Child stderr is not piped"))?;
+
+    let wait = async {
+        tokio::try_join!(
+            async { Ok(child.wait().await?) },
+            read_bounded(&mut stdout, stdout_limit),
+            read_bounded(&mut stderr, stderr_limit),
+        )
+    };
+    let err = match timeout(time_limit, wait).await {
+        Ok(Ok((status, stdout, stderr))) => {
+            return Ok(Output {
+                status,
+                stdout,
+                stderr,
+            });
+        }
+        Ok(Err(err)) => err,
+        Err(_) => HandlerError::TimeLimitExceeded,
+    };
+
+    child.kill().await?;
+    Err(err)
+}
+
+/// Read `reader` to the end, failing as soon as more than `limit` bytes arrive
+async fn read_bounded(
+    reader: &mut (impl AsyncRead + Unpin),
+    limit: usize,
+) -> Result<Vec<u8>, HandlerError> {
+    let mut buf = Vec::new();
+    // One byte past the limit tells it was exceeded
+    reader
+        .take((limit as u64).saturating_add(1))
+        .read_to_end(&mut buf)
+        .await?;
+
+    if buf.len() > limit {
+        return Err(HandlerError::OutputLimitExceeded);
+    }
+
+    Ok(buf)
+}
+
+/// Poll the memory usage in `usage_path` forever, keeping the highest value seen in `peak_bytes`
+///
+/// Run it alongside the child: spikes are then captured even where the kernel peak is missing.
+/// See [`crate::utils::memory_usage_path`] for the file of a cgroup.
+pub async fn sample_memory_peak(usage_path: &Path, period: Duration, peak_bytes: &mut u64) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+
+        // The cgroup may be torn down under us, a failed sample is simply skipped
+        if let Ok(usage) = tokio::fs::read_to_string(usage_path).await
+            && let Ok(usage) = usage.trim().parse::<u64>()
+        {
+            *peak_bytes = (*peak_bytes).max(usage);
+        }
+    }
+}
+
+/// Poll the cgroup's live process and thread count forever, keeping the highest seen in `peak`
+pub async fn sample_pids_peak(controller: &PidController, period: Duration, peak: &mut u64) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+
+        if let Ok(current) = controller.get_pid_current() {
+            *peak = (*peak).max(current);
         }
     }
 }
@@ -48,7 +353,20 @@ impl From<HandlerError> for JudgeResult {
 pub struct ExecutionContext {
     pub work_dir: PathBuf,
     pub source_file: PathBuf,
+    /// Further files of the submission, next to the source
+    pub extra_files: Vec<PathBuf>,
+    /// Program a native compiler builds and the judged program runs
     pub executable_file: PathBuf,
+    /// Directory of the compiled classes, for handlers running on the JVM
+    pub class_path: Option<PathBuf>,
+    /// Extra environment variables for the judged program
+    pub env: Vec<(String, String)>,
+    /// Request being judged, named in the cgroups so leftovers can be traced back to it
+    pub request_id: Option<usize>,
+    /// Run the program as [`constants::SANDBOX_UID`], owner of the work directory
+    ///
+    /// Only trusted programs such as checkers keep the agent's user.
+    pub unprivileged: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -56,59 +374,240 @@ pub struct CompileInfo {
     pub status_code: ExitStatus,
     pub stdout: String,
     pub stderr: String,
+    /// Cpu time of the compiler and its subprocesses
+    pub compile_time_ms: u64,
+    pub compile_memory_kib: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct ExecuteInfo {
     pub status_code: ExitStatus,
-    pub stdout: String,
+    /// Signal that terminated the program, if any
+    pub signal: Option<i32>,
+    /// Raw stdout, compared as is by [`shared::rpc::CompareMode::Exact`]
+    pub stdout: Vec<u8>,
     pub stderr: String,
     pub resource_usage: ResourceUsage,
 }
 
+impl ExecuteInfo {
+    /// Stdout for display and text comparison, invalid UTF-8 replaced
+    pub fn stdout_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.stdout)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompileLimits {
+    pub time_ms: u64,
+    pub memory_kib: u64,
+}
+
+impl Default for CompileLimits {
+    fn default() -> Self {
+        Self {
+            time_ms: constants::DEFAULT_COMPILE_TIME_LIMIT_MS,
+            memory_kib: constants::DEFAULT_COMPILE_MEMORY_LIMIT_KIB,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExecuteLimits {
+    /// Cpu time limit, only checked once the program exits
+    pub time_ms: u64,
+    /// Wall-clock time limit, the program is killed when it elapses
+    pub real_time_ms: u64,
+    pub memory_kib: u64,
+    pub stack_kib: u64,
+    pub stdout_bytes: usize,
+    pub stderr_bytes: usize,
+    /// Limit on files written into the work directory, none for no quota
+    pub disk_kib: Option<u64>,
+    /// Limit on live processes and threads, including the program itself
+    pub pids: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct ResourceUsage {
     pub memory_kib: u64,
     pub real_time_ms: u64,
     pub cpu_time_ms: u64,
+    /// Highest number of live processes and threads seen
+    pub max_pids: u64,
 }
 
 /// Language related handler
-pub trait Handler {
+///
+/// Futures must be [`Send`], so handlers can be boxed into a [`crate::registry::HandlerRegistry`].
+/// Implementations can still use `async fn`.
+pub trait Handler: Send + Sync {
     /// Whether the handler needs compilation
     ///
     /// If false, [`Handler::compile`] will not be called
     fn needs_compile(&self) -> bool;
 
+    /// Seccomp profile the judged program runs under in [`Handler::execute`]
+    fn seccomp_profile(&self) -> SeccompProfile;
+
     /// Prepare the environment for compilation
-    async fn prepare(&self, source_code: &str) -> Result<ExecutionContext, HandlerError>;
+    fn prepare(
+        &self,
+        source_code: &str,
+    ) -> impl Future<Output = Result<ExecutionContext, HandlerError>> + Send;
 
     /// Compile the source code
     ///
     /// Return [`Option::None`] if compilation is not needed
-    async fn compile(
+    fn compile(
         &self,
         context: &ExecutionContext,
-        time_limit_ms: u64,
-    ) -> Result<Option<CompileInfo>, HandlerError>;
+        options: &CompilerOptions,
+        limits: &CompileLimits,
+    ) -> impl Future<Output = Result<Option<CompileInfo>, HandlerError>> + Send;
 
     /// Execute the compiled program once
     ///
-    /// Handler should handle time limit and memory limit
+    /// Handler should handle every limit in [`ExecuteLimits`], running the program inside the work
+    /// directory so the disk quota covers its relative writes
     ///
     /// Note: stderr is for debugging (user), stdout is for judging (expected output comparison)
-    async fn execute(
+    fn execute(
         &self,
         context: &ExecutionContext,
         input_data: &str,
-        time_limit_ms: u64,
-        memory_limit_kib: u64,
-        stdout_limit_bytes: usize,
-        stderr_limit_bytes: usize,
-    ) -> Result<ExecuteInfo, HandlerError>;
+        limits: &ExecuteLimits,
+    ) -> impl Future<Output = Result<ExecuteInfo, HandlerError>> + Send;
 
     /// Cleanup the environment
     ///
     /// REVIEW: Should we use it? The agent will only be executed once and then the MicroVM will be destroyed
-    async fn cleanup(&self, context: &ExecutionContext) -> Result<(), HandlerError>;
+    fn cleanup(
+        &self,
+        context: &ExecutionContext,
+    ) -> impl Future<Output = Result<(), HandlerError>> + Send;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use tokio::process::Command;
+
+    fn spawn(program: &str, args: &[&str]) -> Child {
+        Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn kills_and_reaps_children_past_the_time_limit() {
+        let child = spawn("sleep", &["10"]);
+        let pid = child.id().unwrap() as libc::pid_t;
+
+        let result = wait_with_timeout(child, Duration::from_millis(100), 1024, 1024).await;
+
+        assert!(matches!(result, Err(HandlerError::TimeLimitExceeded)));
+        // Reaped, not even a zombie is left
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+    }
+
+    #[tokio::test]
+    async fn collects_output_of_children_finishing_in_time() {
+        let child = spawn("sh", &["-c", "echo out; echo err >&2; exit 3"]);
+
+        let output = wait_with_timeout(child, Duration::from_secs(10), 1024, 1024)
+            .await
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(
+            (&output.stdout[..], &output.stderr[..]),
+            (&b"out\n"[..], &b"err\n"[..])
+        );
+    }
+
+    #[tokio::test]
+    async fn read_bounded_fails_one_byte_past_the_limit() {
+        let mut exact: &[u8] = b"1234";
+        let mut over: &[u8] = b"12345";
+
+        assert_eq!(read_bounded(&mut exact, 4).await.unwrap(), b"1234");
+        assert!(matches!(
+            read_bounded(&mut over, 4).await,
+            Err(HandlerError::OutputLimitExceeded)
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_failed_adds_until_one_succeeds() {
+        let mut attempts = 0;
+        let result = retry_add(|| {
+            attempts += 1;
+            if attempts < 3 { Err("busy") } else { Ok(()) }
+        })
+        .await;
+
+        assert_eq!((result, attempts), (Ok(()), 3));
+    }
+
+    #[tokio::test]
+    async fn gives_up_adding_after_the_last_retry() {
+        let mut attempts = 0;
+        let result = retry_add(|| {
+            attempts += 1;
+            Err("busy")
+        })
+        .await;
+
+        assert_eq!((result, attempts), (Err("busy"), 5));
+    }
+
+    #[tokio::test]
+    async fn work_dirs_are_removed_unless_kept() {
+        let dropped = sandbox::prepare("", "input.cpp").await.unwrap();
+        let kept = sandbox::prepare("", "input.cpp").await.unwrap();
+        let dropped_dir = dropped.work_dir.clone();
+
+        drop(WorkDirGuard::new(dropped));
+        let kept = WorkDirGuard::new(kept).keep();
+        let kept_exists = kept.work_dir.exists();
+        sandbox::cleanup(&kept).await.unwrap();
+
+        assert!(!dropped_dir.exists());
+        assert!(kept_exists);
+    }
+
+    #[tokio::test]
+    async fn memory_sampler_captures_short_spikes() {
+        let usage = tempfile::NamedTempFile::new().unwrap();
+        let mut peak_bytes = 0;
+        let sampler = sample_memory_peak(usage.path(), Duration::from_millis(1), &mut peak_bytes);
+        let workload = async {
+            for bytes in ["1024", "8192", "2048"] {
+                tokio::fs::write(usage.path(), bytes).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+
+        tokio::select! {
+            () = sampler => unreachable!("memory sampler never finishes"),
+            () = workload => {}
+        }
+        assert_eq!(peak_bytes, 8192);
+    }
+
+    #[tokio::test]
+    async fn stops_children_flooding_their_output() {
+        // Never exits on its own, only the output limit ends it before the time limit
+        let child = spawn("yes", &[]);
+
+        let result = wait_with_timeout(child, Duration::from_secs(10), 1024, 1024).await;
+
+        assert!(matches!(result, Err(HandlerError::OutputLimitExceeded)));
+    }
 }
@@ -0,0 +1,80 @@
+use crate::{
+    constants,
+    handler::{
+        CompileInfo, CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext, Handler,
+        HandlerError, sandbox,
+    },
+    seccomp::SeccompProfile,
+};
+use shared::rpc::CompilerOptions;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Runs `main.py` with `python3`, without a compile step
+///
+/// The interpreter loads shared libraries and modules at startup, which
+/// [`SeccompProfile::Strict`] forbids, so programs run under the relaxed
+/// [`SeccompProfile::Compile`] instead.
+#[derive(Debug, Clone, Copy)]
+pub struct PythonHandler {
+    /// Interval between memory usage samples while the program runs
+    pub memory_sample_interval: Duration,
+}
+
+impl Default for PythonHandler {
+    fn default() -> Self {
+        Self {
+            memory_sample_interval: Duration::from_millis(
+                constants::DEFAULT_MEMORY_SAMPLE_INTERVAL_MS,
+            ),
+        }
+    }
+}
+
+impl Handler for PythonHandler {
+    fn needs_compile(&self) -> bool {
+        false
+    }
+
+    fn seccomp_profile(&self) -> SeccompProfile {
+        SeccompProfile::Compile
+    }
+
+    async fn prepare(&self, source_code: &str) -> Result<ExecutionContext, HandlerError> {
+        sandbox::prepare(source_code, "main.py").await
+    }
+
+    async fn compile(
+        &self,
+        _context: &ExecutionContext,
+        _options: &CompilerOptions,
+        _limits: &CompileLimits,
+    ) -> Result<Option<CompileInfo>, HandlerError> {
+        Ok(None)
+    }
+
+    async fn execute(
+        &self,
+        context: &ExecutionContext,
+        input_data: &str,
+        limits: &ExecuteLimits,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        let mut command = Command::new("python3");
+        command.arg(&context.source_file);
+
+        sandbox::execute(
+            command,
+            "python",
+            context,
+            input_data,
+            limits,
+            self.memory_sample_interval,
+            self.seccomp_profile(),
+        )
+        .await
+    }
+
+    async fn cleanup(&self, context: &ExecutionContext) -> Result<(), HandlerError> {
+        sandbox::cleanup(context).await
+    }
+}
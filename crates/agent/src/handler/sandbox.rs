@@ -0,0 +1,953 @@
+//! Sandboxed steps shared by handlers: work directory, cgroup-limited compile and execution
+
+use crate::{
+    constants,
+    handler::{
+        CgroupGuard, CompileInfo, CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext,
+        HandlerError, ResourceUsage, add_task_with_retry, sample_memory_peak, sample_pids_peak,
+        wait_with_timeout,
+    },
+    seccomp::{SeccompFilter, SeccompProfile},
+    utils::{self, CpuStats},
+};
+use cgroups_rs::fs::{
+    MaxValue, cgroup_builder::CgroupBuilder, cpu::CpuController, hierarchies,
+    memory::MemController, pid::PidController,
+};
+use shared::rpc::{CompilerOptions, OptLevel, SourceFile};
+use std::{
+    io::ErrorKind,
+    os::unix::{ffi::OsStrExt, process::ExitStatusExt},
+    process::{ExitStatus, Stdio},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tempfile::Builder;
+use tokio::{
+    fs::remove_dir_all,
+    io::AsyncWriteExt,
+    process::Command,
+    time::{Instant, timeout},
+};
+use tokio_retry::{
+    Retry,
+    strategy::{ExponentialBackoff, jitter},
+};
+
+/// Create a fresh work directory holding the source code as `source_name`
+pub async fn prepare(
+    source_code: &str,
+    source_name: &str,
+) -> Result<ExecutionContext, HandlerError> {
+    let temp_dir = Builder::new()
+        .prefix(constants::RESOURCE_PREFIX)
+        .tempdir()?
+        .keep();
+    let source_code_path = temp_dir.join(source_name);
+    let executable_path = temp_dir.join("output.executable");
+
+    tokio::fs::File::create_new(&source_code_path)
+        .await?
+        .write_all(source_code.as_bytes())
+        .await?;
+
+    Ok(ExecutionContext {
+        work_dir: temp_dir,
+        source_file: source_code_path,
+        extra_files: Vec::new(),
+        executable_file: executable_path,
+        class_path: None,
+        env: Vec::new(),
+        request_id: None,
+        unprivileged: true,
+    })
+}
+
+/// Longest file name most Linux file systems accept (`NAME_MAX`)
+const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// Write a submission's further files into the work directory, see [`JudgeRequest::files`]
+///
+/// Names must be plain file names not taken yet, so nothing is written outside the work directory
+/// or over the source. Names the file system refuses, such as ones over [`MAX_FILE_NAME_BYTES`],
+/// are invalid as well rather than failures of the agent.
+pub async fn write_files(
+    context: &mut ExecutionContext,
+    files: &[SourceFile],
+) -> Result<(), HandlerError> {
+    for file in files {
+        let is_valid = !file.name.is_empty()
+            && file.name.len() <= MAX_FILE_NAME_BYTES
+            && file.name != "."
+            && file.name != ".."
+            && !file.name.contains(['/', '\\', '\0']);
+        if !is_valid {
+            return Err(HandlerError::InvalidFileName(file.name.clone()));
+        }
+
+        let path = context.work_dir.join(&file.name);
+        if path == context.executable_file || context.class_path.as_ref() == Some(&path) {
+            return Err(HandlerError::InvalidFileName(file.name.clone()));
+        }
+        let mut created = match tokio::fs::File::create_new(&path).await {
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::AlreadyExists | ErrorKind::InvalidFilename
+                ) =>
+            {
+                return Err(HandlerError::InvalidFileName(file.name.clone()));
+            }
+            created => created?,
+        };
+        created.write_all(file.content.as_bytes()).await?;
+        context.extra_files.push(path);
+    }
+
+    Ok(())
+}
+
+/// GCC family compiler invocation building the context's executable
+///
+/// Further files with one of `unit_extensions` are compiled along with the source. Requested
+/// [`CompilerOptions::extra_flags`] follow the handler's `extra_args`, so they win over them, but
+/// never over the source and output paths. With `sandbox`, the compiler runs under
+/// [`SeccompFilter::apply_compile_filter`] and keeps its temporary files inside the work
+/// directory.
+pub fn gcc_command(
+    compiler: &str,
+    context: &ExecutionContext,
+    options: &CompilerOptions,
+    extra_args: &[&str],
+    unit_extensions: &[&str],
+    sandbox: bool,
+) -> Result<Command, HandlerError> {
+    if let Some(flag) = options
+        .extra_flags
+        .iter()
+        .find(|flag| !is_allowed_flag(flag))
+    {
+        return Err(HandlerError::ForbiddenCompilerFlag(flag.clone()));
+    }
+
+    // Using the requested optimization level and suppressing warnings
+    let mut command = Command::new(compiler);
+    command
+        .arg("-w")
+        .arg(optimization_flag(options.optimization))
+        .args(extra_args);
+    if options.debug_info {
+        command.arg("-g");
+    }
+    command
+        .args(&options.extra_flags)
+        .arg(&context.source_file)
+        .args(context.extra_files.iter().filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| unit_extensions.contains(&extension))
+        }))
+        .arg("-o")
+        .arg(&context.executable_file);
+
+    if sandbox {
+        unsafe {
+            command
+                .current_dir(&context.work_dir)
+                .env("TMPDIR", &context.work_dir)
+                .pre_exec(SeccompFilter::apply_compile_filter);
+        }
+    }
+
+    Ok(command)
+}
+
+/// Prefixes of the flags a request may pass: language standard, macros, warnings, sanitizers,
+/// debug information, optimization and target
+///
+/// Anything else could load code into the compiler or linker, or read and write files outside the
+/// work directory (e.g. `-fplugin`, `-B`, `-o`, `-MF` or `-Xlinker`), so an allow-list it is.
+const ALLOWED_FLAG_PREFIXES: [&str; 9] = [
+    "-std=",
+    "-D",
+    "-U",
+    "-W",
+    "-fsanitize=",
+    "-fno-sanitize=",
+    "-g",
+    "-O",
+    "-march=",
+];
+
+/// Pass-through prefixes among the `-W` ones, forwarding arbitrary options to the assembler,
+/// preprocessor or linker
+const PASS_THROUGH_FLAG_PREFIXES: [&str; 3] = ["-Wa,", "-Wp,", "-Wl,"];
+
+/// Whether a requested compiler flag is safe to pass, see [`ALLOWED_FLAG_PREFIXES`]
+fn is_allowed_flag(flag: &str) -> bool {
+    ALLOWED_FLAG_PREFIXES
+        .iter()
+        .any(|prefix| flag.starts_with(prefix))
+        && !PASS_THROUGH_FLAG_PREFIXES
+            .iter()
+            .any(|prefix| flag.starts_with(prefix))
+}
+
+/// Run a compiler command inside a cgroup named after `name`, enforcing `limits`
+pub async fn compile(
+    mut command: Command,
+    name: &str,
+    context: &ExecutionContext,
+    limits: &CompileLimits,
+) -> Result<CompileInfo, HandlerError> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let cmd = match command.spawn() {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(HandlerError::InternalError("Compiler not found"));
+        }
+        cmd => cmd?,
+    };
+
+    let pid = cmd
+        .id()
+        .ok_or(HandlerError::InternalError("Cannot get compiler pid"))?;
+
+    // Create cgroup for compilation, the guard deletes it on every early return
+    let hier = hierarchies::auto();
+    let cg = CgroupBuilder::new(&cgroup_name(context, name, "compile", pid))
+        .cpu()
+        .done()
+        .memory()
+        .memory_hard_limit((limits.memory_kib * 1024) as i64)
+        .done()
+        .build(hier)
+        .map(CgroupGuard::new)?;
+    add_task_with_retry(&cg, pid).await?;
+
+    // Wait output
+    let time_limit = Duration::from_millis(limits.time_ms);
+    let output = wait_with_timeout(cmd, time_limit, usize::MAX, usize::MAX).await?;
+
+    // Check if compiler was killed by OOM
+    let memory_controller: &MemController = cg.controller_of().unwrap();
+    let memory_stat = memory_controller.memory_stat();
+    if memory_stat.fail_cnt > 0 {
+        return Err(HandlerError::MemoryLimitExceeded);
+    }
+
+    let compile_memory_bytes = utils::memory_peak_bytes(memory_controller, cg.v2());
+    let cpu_controller: &CpuController = cg.controller_of().unwrap();
+    let cpu = CpuStats::from_str(&cpu_controller.cpu().stat)?;
+
+    cg.delete().await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    // A compiler bug is not the submission's fault
+    if is_compiler_crash(output.status, &stderr) {
+        return Err(HandlerError::CompilerCrashed(stderr));
+    }
+
+    Ok(CompileInfo {
+        status_code: output.status,
+        stdout: String::from_utf8_lossy(&output.stdout).into(),
+        stderr,
+        compile_time_ms: cpu.usage_usec / 1000,
+        compile_memory_kib: compile_memory_bytes.div_ceil(1024),
+    })
+}
+
+/// Whether the compiler exited with `status` because of a bug of its own
+///
+/// Killed by a signal, or GCC exiting with 4 after its internal compiler error report. The
+/// report alone is not enough, the submission controls part of stderr, e.g. with `#error`.
+fn is_compiler_crash(status: ExitStatus, stderr: &str) -> bool {
+    if status.signal().is_some() {
+        return true;
+    }
+
+    status.code() == Some(4)
+        && stderr.contains("internal compiler error:")
+        && stderr.contains("Please submit a full bug report")
+}
+
+/// Run the judged program once inside a cgroup named after `name`, enforcing `limits`
+///
+/// `command` only names the program and its arguments, the work directory, environment, stdio,
+/// user and the seccomp `profile` are set up here. See [`ExecutionContext::unprivileged`].
+pub async fn execute(
+    mut command: Command,
+    name: &str,
+    context: &ExecutionContext,
+    input_data: &str,
+    limits: &ExecuteLimits,
+    memory_sample_interval: Duration,
+    profile: SeccompProfile,
+) -> Result<ExecuteInfo, HandlerError> {
+    let now = Instant::now();
+    let stack_bytes = limits.stack_kib * 1024;
+    let unprivileged = context.unprivileged;
+    // Standard library commands exec this very string, see `SeccompFilter::apply_strict_filter`
+    let program = command.as_std().get_program().as_bytes().as_ptr() as usize;
+
+    // The sandbox user may write its work directory, every other one of the agent stays private
+    if unprivileged {
+        std::os::unix::fs::chown(
+            &context.work_dir,
+            Some(constants::SANDBOX_UID),
+            Some(constants::SANDBOX_GID),
+        )?;
+    }
+
+    let mut cmd = unsafe {
+        command
+            .current_dir(&context.work_dir)
+            .envs(context.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .pre_exec(move || {
+                utils::set_stack_limit(stack_bytes)?;
+                if unprivileged {
+                    utils::drop_privileges(constants::SANDBOX_UID, constants::SANDBOX_GID)?;
+                }
+                profile.apply(program as *const u8)
+            })
+            .spawn()?
+    };
+
+    let pid = cmd
+        .id()
+        .ok_or(HandlerError::InternalError("Cannot get child process pid"))?;
+
+    // Create cgroup to limit and gather resource usage, deleted on early returns by the guard
+    let hier = hierarchies::auto();
+    let cg = CgroupBuilder::new(&cgroup_name(context, name, "execute", pid))
+        .cpu()
+        .done()
+        .memory()
+        .memory_hard_limit((limits.memory_kib * 1024) as i64)
+        .done()
+        .pid()
+        .maximum_number_of_processes(MaxValue::Value(limits.pids as i64))
+        .done()
+        .build(hier)
+        .map(CgroupGuard::new)?;
+    add_task_with_retry(&cg, pid).await?;
+    let memory_controller: &MemController = cg.controller_of().unwrap();
+    let cpu_controller: &CpuController = cg.controller_of().unwrap();
+    let pid_controller: &PidController = cg.controller_of().unwrap();
+
+    // Sample memory usage and process count while waiting for the child
+    let mut sampled_peak_bytes = 0u64;
+    let mut max_pids = 1u64;
+    let usage_path = utils::memory_usage_path(memory_controller, cg.v2());
+    let memory_sampler =
+        sample_memory_peak(&usage_path, memory_sample_interval, &mut sampled_peak_bytes);
+    let pids_sampler = sample_pids_peak(pid_controller, memory_sample_interval, &mut max_pids);
+    // Feed stdin while output is drained, a program filling its stdout pipe before reading all
+    // of its input would otherwise deadlock against us. Failing to write is not our error: the
+    // program may exit, or be killed, without reading everything.
+    let time_limit = Duration::from_millis(limits.real_time_ms);
+    let mut stdin = cmd.stdin.take().unwrap();
+    let feed = timeout(time_limit, async move {
+        let _ = stdin.write_all(input_data.as_bytes()).await;
+    });
+    let wait = async {
+        let (output, _) = tokio::join!(
+            wait_with_timeout(cmd, time_limit, limits.stdout_bytes, limits.stderr_bytes),
+            feed,
+        );
+        output
+    };
+
+    let output = tokio::select! {
+        output = wait => output?,
+        () = memory_sampler => unreachable!("memory sampler never finishes"),
+        () = pids_sampler => unreachable!("pids sampler never finishes"),
+    };
+
+    // Check OOM kill status
+    let memory_stat = memory_controller.memory_stat();
+    if memory_stat.fail_cnt > 0 {
+        return Err(HandlerError::MemoryLimitExceeded);
+    }
+
+    // Check whether a fork or thread creation was refused by the pid limit
+    if pid_controller.get_pid_events()? > 0 {
+        return Err(HandlerError::PidLimitExceeded);
+    }
+
+    // Check memory usage
+    let memory = utils::memory_peak_bytes(memory_controller, cg.v2()).max(sampled_peak_bytes);
+    if memory > limits.memory_kib * 1024 {
+        return Err(HandlerError::MemoryLimitExceeded);
+    }
+
+    // Check files written into the work directory, excluding the submission and what was built
+    if let Some(disk_kib) = limits.disk_kib {
+        let mut excluded = vec![context.source_file.as_path(), &context.executable_file];
+        excluded.extend(context.class_path.as_deref());
+        excluded.extend(context.extra_files.iter().map(|path| path.as_path()));
+        let written = utils::dir_size(&context.work_dir, &excluded).await?;
+        if written > disk_kib * 1024 {
+            return Err(HandlerError::DiskLimitExceeded);
+        }
+    }
+
+    let cpu = cpu_controller.cpu().stat;
+    let cpu = CpuStats::from_str(&cpu)?;
+
+    cg.delete().await?;
+
+    Ok(ExecuteInfo {
+        status_code: output.status,
+        signal: output.status.signal(),
+        stdout: output.stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).into(),
+        resource_usage: ResourceUsage {
+            memory_kib: memory.div_ceil(1024),
+            real_time_ms: now.elapsed().as_millis() as u64,
+            cpu_time_ms: cpu.usage_usec / 1000,
+            max_pids,
+        },
+    })
+}
+
+/// Name of the cgroup of one `step` of `name`, never reused while the agent runs
+///
+/// Pids alone are not enough, they are recycled quickly.
+fn cgroup_name(context: &ExecutionContext, name: &str, step: &str, pid: u32) -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let request = context
+        .request_id
+        .map_or_else(String::new, |id| format!("{id}-"));
+
+    format!(
+        "{}{request}{name}-{step}-{pid}-{sequence}",
+        constants::RESOURCE_PREFIX
+    )
+}
+
+/// Remove the work directory with everything the compiler or program left in it
+pub async fn cleanup(context: &ExecutionContext) -> Result<(), HandlerError> {
+    let retry_strategy = ExponentialBackoff::from_millis(100).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        remove_dir_all(&context.work_dir).await?;
+        Ok(())
+    })
+    .await
+}
+
+/// GCC flag selecting the optimization level
+fn optimization_flag(level: OptLevel) -> &'static str {
+    match level {
+        OptLevel::O0 => "-O0",
+        OptLevel::O1 => "-O1",
+        OptLevel::O2 => "-O2",
+        OptLevel::O3 => "-O3",
+        OptLevel::Os => "-Os",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        ffi::OsStr,
+        path::{Path, PathBuf},
+    };
+
+    fn context() -> ExecutionContext {
+        let work_dir = PathBuf::from("/work");
+        ExecutionContext {
+            source_file: work_dir.join("input.cpp"),
+            extra_files: vec![work_dir.join("lib.cpp"), work_dir.join("lib.h")],
+            executable_file: work_dir.join("output.executable"),
+            class_path: None,
+            work_dir,
+            env: Vec::new(),
+            request_id: None,
+            unprivileged: true,
+        }
+    }
+
+    fn args(command: &Command) -> Vec<&OsStr> {
+        command.as_std().get_args().collect()
+    }
+
+    #[test]
+    fn allows_standard_macro_warning_and_sanitizer_flags() {
+        for flag in [
+            "-std=c++20",
+            "-DLOCAL",
+            "-UNDEBUG",
+            "-Wall",
+            "-Wlogical-op",
+            "-fsanitize=address",
+            "-fno-sanitize=all",
+            "-g3",
+            "-Ofast",
+            "-march=native",
+        ] {
+            assert!(is_allowed_flag(flag), "{flag} should be allowed");
+        }
+    }
+
+    #[test]
+    fn rejects_flags_moving_output_or_loading_code() {
+        for flag in [
+            "-o",
+            "-o/tmp/x",
+            "-Wl,-o,/tmp/x",
+            "-Wl,-plugin=/tmp/x.so",
+            "-Wa,-adhln=/tmp/x",
+            "-Wp,-MD,/tmp/x",
+            "-Xlinker",
+            "--output=/tmp/x",
+            "-MF/tmp/x",
+            "-MD",
+            "-dumpdir",
+            "-fdump-tree-all",
+            "-fprofile-generate",
+            "-fplugin=/tmp/x.so",
+            "-B/tmp",
+            "-specs=/tmp/x",
+            "@/tmp/args",
+            "/etc/passwd",
+            "",
+        ] {
+            assert!(!is_allowed_flag(flag), "{flag} should be rejected");
+        }
+    }
+
+    const ICE_REPORT: &str = "input.cpp:3:1: internal compiler error: Segmentation fault\n\
+        Please submit a full bug report, with preprocessed source.\n";
+
+    #[test]
+    fn detects_compiler_crashes_from_exit_code_and_report() {
+        assert!(is_compiler_crash(ExitStatus::from_raw(4 << 8), ICE_REPORT));
+        assert!(is_compiler_crash(ExitStatus::from_raw(libc::SIGSEGV), ""));
+    }
+
+    #[test]
+    fn ignores_crash_reports_of_failed_compilations() {
+        // What `#error internal compiler error: ...` in the submission looks like
+        assert!(!is_compiler_crash(ExitStatus::from_raw(1 << 8), ICE_REPORT));
+        assert!(!is_compiler_crash(
+            ExitStatus::from_raw(4 << 8),
+            "input.cpp:1:2: error: #error internal compiler error:\n"
+        ));
+        assert!(!is_compiler_crash(ExitStatus::from_raw(0), ""));
+    }
+
+    #[test]
+    fn gcc_command_appends_flags_after_defaults_and_before_paths() {
+        let options = CompilerOptions {
+            extra_flags: vec!["-std=c++17".into(), "-DLOCAL".into()],
+            ..CompilerOptions::default()
+        };
+        let command =
+            gcc_command("g++", &context(), &options, &["-static"], &["cpp"], false).unwrap();
+
+        let expected: Vec<&OsStr> = [
+            "-w",
+            "-O2",
+            "-static",
+            "-std=c++17",
+            "-DLOCAL",
+            "/work/input.cpp",
+            "/work/lib.cpp",
+            "-o",
+            "/work/output.executable",
+        ]
+        .iter()
+        .map(OsStr::new)
+        .collect();
+        assert_eq!(args(&command), expected);
+    }
+
+    #[tokio::test]
+    async fn missing_compilers_are_an_internal_error() {
+        let command = Command::new("/nonexistent/g++");
+        let result = compile(command, "cpp", &context(), &CompileLimits::default()).await;
+
+        assert!(matches!(
+            result,
+            Err(HandlerError::InternalError("Compiler not found"))
+        ));
+    }
+
+    #[test]
+    fn gcc_command_follows_the_requested_optimization_and_debug_info() {
+        let options = CompilerOptions {
+            optimization: OptLevel::O0,
+            debug_info: true,
+            ..CompilerOptions::default()
+        };
+        let command = gcc_command("gcc", &context(), &options, &[], &["c"], false).unwrap();
+
+        let args = args(&command);
+        assert_eq!(args[..3], ["-w", "-O0", "-g"]);
+        assert!(!args.contains(&OsStr::new("-O2")));
+    }
+
+    #[test]
+    fn sandboxed_compilers_keep_to_the_work_directory() {
+        let options = CompilerOptions::default();
+        let sandboxed = gcc_command("g++", &context(), &options, &[], &[], true).unwrap();
+        let trusted = gcc_command("g++", &context(), &options, &[], &[], false).unwrap();
+
+        let tmpdir = |command: &Command| {
+            command
+                .as_std()
+                .get_envs()
+                .find(|(key, _)| *key == "TMPDIR")
+                .and_then(|(_, value)| value)
+                .map(PathBuf::from)
+        };
+        assert_eq!(
+            sandboxed.as_std().get_current_dir(),
+            Some(Path::new("/work"))
+        );
+        assert_eq!(tmpdir(&sandboxed), Some(PathBuf::from("/work")));
+        assert_eq!(trusted.as_std().get_current_dir(), None);
+        assert_eq!(tmpdir(&trusted), None);
+    }
+
+    #[tokio::test]
+    async fn compiles_under_the_compile_profile() {
+        let source = "#include <cstdio>\nint main() { std::puts(\"hello\"); }\n";
+        let context = prepare(source, "input.cpp").await.unwrap();
+        let options = CompilerOptions::default();
+        let mut command =
+            gcc_command("g++", &context, &options, &["-static"], &["cpp"], true).unwrap();
+
+        let status = command.status().await.unwrap();
+        let built = context.executable_file.exists();
+        cleanup(&context).await.unwrap();
+
+        assert!(status.success());
+        assert!(built);
+    }
+
+    #[test]
+    fn gcc_command_rejects_forbidden_flags() {
+        let options = CompilerOptions {
+            extra_flags: vec!["-Wall".into(), "-Wl,-o,/tmp/x".into()],
+            ..CompilerOptions::default()
+        };
+        let result = gcc_command("g++", &context(), &options, &[], &["cpp"], false);
+
+        assert!(matches!(
+            result,
+            Err(HandlerError::ForbiddenCompilerFlag(flag)) if flag == "-Wl,-o,/tmp/x"
+        ));
+    }
+
+    fn file(name: &str) -> SourceFile {
+        SourceFile {
+            name: name.into(),
+            content: "content".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_files_next_to_the_source() {
+        let mut context = prepare("", "input.cpp").await.unwrap();
+        let written = write_files(&mut context, &[file("lib.h"), file(".hidden")]).await;
+        let content = tokio::fs::read_to_string(context.work_dir.join("lib.h")).await;
+        cleanup(&context).await.unwrap();
+
+        written.unwrap();
+        assert_eq!(content.unwrap(), "content");
+        assert_eq!(
+            context.extra_files,
+            [
+                context.work_dir.join("lib.h"),
+                context.work_dir.join(".hidden")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_names_escaping_or_overwriting() {
+        let mut context = prepare("", "input.cpp").await.unwrap();
+        let mut rejected = Vec::new();
+        for name in [
+            "",
+            ".",
+            "..",
+            "../lib.h",
+            "/etc/passwd",
+            "dir/lib.h",
+            "..\\lib.h",
+            "lib\0.h",
+            "input.cpp",
+            "output.executable",
+        ] {
+            let result = write_files(&mut context, &[file(name)]).await;
+            rejected.push(matches!(result, Err(HandlerError::InvalidFileName(_))));
+        }
+        cleanup(&context).await.unwrap();
+
+        assert!(rejected.into_iter().all(|rejected| rejected));
+        assert!(context.extra_files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_names_taking_the_class_path() {
+        let mut context = prepare("", "Main.java").await.unwrap();
+        context.class_path = Some(context.work_dir.join("classes"));
+        let result = write_files(&mut context, &[file("classes")]).await;
+        cleanup(&context).await.unwrap();
+
+        assert!(matches!(result, Err(HandlerError::InvalidFileName(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_names_too_long_for_the_file_system() {
+        let mut context = prepare("", "input.cpp").await.unwrap();
+        let longest = write_files(&mut context, &[file(&"a".repeat(MAX_FILE_NAME_BYTES))]).await;
+        let too_long = write_files(&mut context, &[file(&"a".repeat(256))]).await;
+        cleanup(&context).await.unwrap();
+
+        longest.unwrap();
+        assert!(matches!(too_long, Err(HandlerError::InvalidFileName(_))));
+    }
+
+    #[test]
+    fn cgroup_names_are_unique_and_traceable() {
+        let mut context = context();
+        context.request_id = Some(42);
+        let first = cgroup_name(&context, "cpp", "execute", 100);
+        let second = cgroup_name(&context, "cpp", "execute", 100);
+
+        assert_ne!(first, second);
+        assert!(first.starts_with("judge-42-cpp-execute-100-"));
+        assert!(cgroup_name(&self::context(), "cpp", "compile", 100).starts_with("judge-cpp-"));
+    }
+
+    fn limits() -> ExecuteLimits {
+        ExecuteLimits {
+            time_ms: 5000,
+            real_time_ms: 5000,
+            memory_kib: 64 * 1024,
+            stack_kib: 8 * 1024,
+            stdout_bytes: 1024,
+            stderr_bytes: 1024,
+            disk_kib: None,
+            pids: 16,
+        }
+    }
+
+    /// Run `script` with `sh` on `input_data` in `context`, under the interpreter profile
+    async fn run_script(
+        context: &ExecutionContext,
+        script: &str,
+        input_data: &str,
+        limits: &ExecuteLimits,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+
+        execute(
+            command,
+            "sh",
+            context,
+            input_data,
+            limits,
+            Duration::from_millis(5),
+            SeccompProfile::Compile,
+        )
+        .await
+    }
+
+    /// Cgroups of request `request_id` still present
+    fn leftover_cgroups(request_id: u64) -> Vec<String> {
+        let prefix = format!("{}{request_id}-", constants::RESOURCE_PREFIX);
+        super::super::judging_cgroups()
+            .into_iter()
+            .filter(|name| name.starts_with(&prefix))
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs root and cgroup v2"]
+    async fn failed_executions_leave_no_cgroup_behind() {
+        let mut context = prepare("", "input.sh").await.unwrap();
+        context.request_id = Some(20471);
+        let result = run_script(&context, "yes", "", &limits()).await;
+        cleanup(&context).await.unwrap();
+
+        assert!(matches!(result, Err(HandlerError::OutputLimitExceeded)));
+        assert_eq!(leftover_cgroups(20471), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs root and cgroup v2"]
+    async fn kills_descendants_outliving_the_program() {
+        let mut context = prepare("", "input.sh").await.unwrap();
+        context.request_id = Some(20472);
+        // Stdin is only fed once the cgroup is set up, so the child is forked inside it
+        let script = "read -r _; sleep 30 </dev/null >/dev/null 2>&1 & echo started";
+        let result = run_script(&context, script, "go\n", &limits()).await;
+        cleanup(&context).await.unwrap();
+
+        assert_eq!(result.unwrap().stdout, b"started\n");
+        assert_eq!(leftover_cgroups(20472), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs root and cgroup v2"]
+    async fn reports_files_written_past_the_disk_quota() {
+        // The submission and what was built are each over the quota, they are not counted
+        let large = "#".repeat(64 * 1024);
+        let mut context = prepare(&large, "input.sh").await.unwrap();
+        let extra = SourceFile {
+            name: "data.txt".into(),
+            content: large.clone(),
+        };
+        write_files(&mut context, &[extra]).await.unwrap();
+        tokio::fs::write(&context.executable_file, &large)
+            .await
+            .unwrap();
+        let class_path = context.work_dir.join("classes");
+        tokio::fs::create_dir(&class_path).await.unwrap();
+        tokio::fs::write(class_path.join("Main.class"), &large)
+            .await
+            .unwrap();
+        context.class_path = Some(class_path);
+        let limits = ExecuteLimits {
+            disk_kib: Some(32),
+            ..limits()
+        };
+
+        let within = run_script(&context, "head -c 16384 /dev/zero > out", "", &limits).await;
+        let past = run_script(&context, "head -c 65536 /dev/zero > out", "", &limits).await;
+        cleanup(&context).await.unwrap();
+
+        within.unwrap();
+        assert!(matches!(past, Err(HandlerError::DiskLimitExceeded)));
+    }
+
+    /// Build `source` with `gcc -static` into the executable of a fresh work directory
+    async fn build_c(source: &str, flags: &[&str]) -> ExecutionContext {
+        let context = prepare(source, "input.c").await.unwrap();
+        let status = Command::new("gcc")
+            .args(["-static", "-O0"])
+            .args(flags)
+            .arg(&context.source_file)
+            .arg("-o")
+            .arg(&context.executable_file)
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success());
+
+        context
+    }
+
+    /// Run the executable of `context` on `input_data`
+    async fn run_program(
+        context: &ExecutionContext,
+        input_data: &str,
+        limits: &ExecuteLimits,
+        profile: SeccompProfile,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        let command = Command::new(&context.executable_file);
+
+        execute(
+            command,
+            "c",
+            context,
+            input_data,
+            limits,
+            Duration::from_millis(5),
+            profile,
+        )
+        .await
+    }
+
+    /// Recurses as deep as its input asks, using a KiB of stack per call
+    const RECURSIVE_PROGRAM: &str = r#"
+        #include <stdio.h>
+        int depth(int n) {
+            volatile char frame[1024];
+            frame[0] = 1;
+            return n == 0 ? 0 : depth(n - 1) + frame[0];
+        }
+        int main(void) {
+            int n;
+            scanf("%d", &n);
+            printf("%d\n", depth(n));
+        }
+    "#;
+
+    #[tokio::test]
+    #[ignore = "needs root and cgroup v2"]
+    async fn deep_recursion_overflows_the_stack_limit() {
+        let context = build_c(RECURSIVE_PROGRAM, &[]).await;
+        let small_stack = ExecuteLimits {
+            stack_kib: 1024,
+            ..limits()
+        };
+        let overflowed = run_program(&context, "4096", &small_stack, SeccompProfile::Strict).await;
+        let fitting = run_program(&context, "4096", &limits(), SeccompProfile::Strict).await;
+        cleanup(&context).await.unwrap();
+
+        assert_eq!(overflowed.unwrap().signal, Some(libc::SIGSEGV));
+        assert_eq!(fitting.unwrap().stdout, b"4096\n");
+    }
+
+    /// Starts as many threads as its input asks, all alive at once for a while
+    const THREADED_PROGRAM: &str = r#"
+        #include <pthread.h>
+        #include <stdio.h>
+        #include <unistd.h>
+        void *run(void *arg) {
+            usleep(200 * 1000);
+            return arg;
+        }
+        int main(void) {
+            int n;
+            pthread_t threads[64];
+            scanf("%d", &n);
+            for (int i = 0; i < n; i++) {
+                if (pthread_create(&threads[i], NULL, run, NULL) != 0) {
+                    return 1;
+                }
+            }
+            for (int i = 0; i < n; i++) {
+                pthread_join(threads[i], NULL);
+            }
+        }
+    "#;
+
+    #[tokio::test]
+    #[ignore = "needs root and cgroup v2"]
+    async fn enforces_and_reports_the_thread_count() {
+        // Threads need clone, which the strict profile refuses
+        let context = build_c(THREADED_PROGRAM, &["-pthread"]).await;
+        let limits = ExecuteLimits {
+            pids: 5,
+            ..limits()
+        };
+        let within = run_program(&context, "4", &limits, SeccompProfile::Compile).await;
+        let past = run_program(&context, "8", &limits, SeccompProfile::Compile).await;
+        cleanup(&context).await.unwrap();
+
+        // The main thread and its 4 threads
+        assert_eq!(within.unwrap().resource_usage.max_pids, 5);
+        assert!(matches!(past, Err(HandlerError::PidLimitExceeded)));
+    }
+}
@@ -1,54 +1,153 @@
+mod cache;
+mod checker;
+mod compare;
 mod constants;
 mod engine;
 mod handler;
+mod registry;
 mod seccomp;
+mod store;
+#[cfg(test)]
+mod testing;
 mod utils;
 
-use crate::{engine::Engine, handler::CppHandler};
+use crate::{
+    checker::PreparedChecker,
+    engine::JudgeError,
+    handler::{CompileLimits, GccHandler, GccLanguage, JavaHandler, PythonHandler},
+    registry::HandlerRegistry,
+};
 use shared::{
-    protocol::{receive_data, send_data},
-    rpc::{JudgeRequest, Language},
+    protocol::{DEFAULT_FRAME_TIMEOUT, receive_data, send_data},
+    rpc::{self, Checker, JudgeRequest, JudgeResult, Language},
 };
+use std::{net::Shutdown, path::Path, time::Duration};
+use tokio::time::timeout;
 use tokio_vsock::{VMADDR_CID_HOST, VsockAddr, VsockStream};
 
 #[tokio::main]
 async fn main() -> Result<(), AgentError> {
+    store::CaseStore::restrict_access(Path::new(constants::DEFAULT_CASE_STORE_DIR))?;
+
     let addr = VsockAddr::new(VMADDR_CID_HOST, constants::DEFAULT_VSOCK_PORT);
     let mut stream = VsockStream::connect(addr).await?;
 
+    let mut registry = HandlerRegistry::new();
+    registry.register(
+        Language::Cpp,
+        GccHandler::new(GccLanguage::CPP),
+        CompileLimits::default(),
+    );
+    registry.register(
+        Language::C,
+        GccHandler::new(GccLanguage::C),
+        CompileLimits::default(),
+    );
+    registry.register(
+        Language::Python,
+        PythonHandler::default(),
+        CompileLimits::default(),
+    );
+    registry.register(
+        Language::Java,
+        JavaHandler::default(),
+        CompileLimits::default(),
+    );
+
     loop {
-        let data = receive_data(&mut stream).await?;
-        let request = postcard::from_bytes::<JudgeRequest>(&data)?;
+        let data = receive_data(
+            &mut stream,
+            constants::MAX_REQUEST_FRAME_BYTES,
+            DEFAULT_FRAME_TIMEOUT,
+        )
+        .await?;
+        let request = match rpc::decode::<JudgeRequest>(&data) {
+            Ok(request) => request,
+            Err(err @ rpc::DecodeError::VersionMismatch { .. }) => {
+                // Tell the host which version this agent speaks before giving up. The id is part
+                // of the foreign layout, so it is not read.
+                let response = JudgeResult::InternalError {
+                    error_message: err.to_string(),
+                }
+                .into_judge_response(0);
+                let result = postcard::to_allocvec(&response)?;
+                send_data(&mut stream, &result, DEFAULT_FRAME_TIMEOUT).await?;
+                stream.shutdown(Shutdown::Both)?;
+                return Err(err.into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let request_id = request.id;
 
         // Spawn judging task
-        let handle = tokio::spawn(async move {
-            match request.language {
-                Language::Cpp => {
-                    Engine::judge(
-                        CppHandler,
-                        request,
-                        constants::DEFAULT_COMPILE_TIME_LIMIT_MS,
-                    )
-                    .await
+        let compile_limits = registry.compile_limits(request.language);
+        let deadline = request_deadline(&request, compile_limits);
+        let mut handle = tokio::spawn(registry.dispatch(request));
+
+        // Wait judging task, infrastructure failures are reported as internal errors
+        let judged = match timeout(deadline, &mut handle).await {
+            Ok(judged) => judged?,
+            Err(_) => {
+                // Aborting drops the task's children, but skips its cleanup
+                handle.abort();
+                let _ = handle.await;
+                handler::sweep_resources();
+
+                Err(JudgeError::Timeout(deadline))
+            }
+        };
+        let response = match judged {
+            Ok(response) => response,
+            Err(err) => {
+                let mut response = JudgeResult::InternalError {
+                    error_message: err.to_string(),
                 }
+                .into_judge_response(request_id);
+                response.is_fatal_error = Some(err.is_fatal());
+                response
             }
-        });
-
-        // Wait judging task
-        let response = handle.await?;
+        };
 
         // Send response
         let is_fatal = response.is_fatal_error.unwrap_or(false);
         let result = postcard::to_allocvec(&response)?;
-        send_data(&mut stream, &result, result.len() as u32).await?;
+        send_data(&mut stream, &result, DEFAULT_FRAME_TIMEOUT).await?;
 
-        // End process on fatal error
+        // Shut down on fatal error, leaving nothing behind for the supervisor
         if is_fatal {
-            std::process::exit(1);
+            handler::sweep_resources();
+            stream.shutdown(Shutdown::Both)?;
+            return Err(AgentError::Fatal);
         }
     }
 }
 
+/// Upper bound on judging `request`, generous enough to never cut a well-behaved judgement
+///
+/// Covers compiling under the `compile_limits` of its handler, none if its language has no
+/// handler, and the checker. Never more than [`constants::MAX_REQUEST_TIME_MS`].
+fn request_deadline(request: &JudgeRequest, compile_limits: Option<CompileLimits>) -> Duration {
+    let checker_ms = match request.checker {
+        Some(_) => constants::DEFAULT_CHECKER_TIME_LIMIT_MS,
+        None => 0,
+    };
+    let cases_ms: u64 = request
+        .test_cases
+        .iter()
+        .map(|case| {
+            let (_, real_time_ms) = engine::time_limits(request, case);
+            real_time_ms + checker_ms + constants::REQUEST_CASE_OVERHEAD_MS
+        })
+        .sum();
+    let checker_compile_ms = match request.checker {
+        Some(Checker::CppSource(_)) => PreparedChecker::compile_limits().time_ms,
+        _ => 0,
+    };
+    let compile_ms = compile_limits.map_or(0, |limits| limits.time_ms) + checker_compile_ms;
+
+    Duration::from_millis((compile_ms + cases_ms).min(constants::MAX_REQUEST_TIME_MS))
+}
+
 #[derive(Debug, thiserror::Error)]
 enum AgentError {
     #[error("{0}")]
@@ -56,5 +155,46 @@ enum AgentError {
     #[error("{0}")]
     Postcard(#[from] postcard::Error),
     #[error("{0}")]
+    Decode(#[from] rpc::DecodeError),
+    #[error("{0}")]
     Join(#[from] tokio::task::JoinError),
+    #[error("Fatal judge error, shutting down")]
+    Fatal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::request;
+
+    #[test]
+    fn deadline_covers_compiling_and_every_case() {
+        let request = request(Language::Cpp, &[("", ""), ("", "")]);
+        let compile_limits = CompileLimits {
+            time_ms: 5_000,
+            memory_kib: 1024,
+        };
+
+        // Cases get twice their 1s cpu time limit as wall-clock time, plus overhead
+        let cases_ms = 2 * (2_000 + constants::REQUEST_CASE_OVERHEAD_MS);
+        assert_eq!(
+            request_deadline(&request, Some(compile_limits)),
+            Duration::from_millis(5_000 + cases_ms)
+        );
+        assert_eq!(
+            request_deadline(&request, None),
+            Duration::from_millis(cases_ms)
+        );
+    }
+
+    #[test]
+    fn deadline_never_exceeds_the_ceiling() {
+        let mut request = request(Language::Cpp, &[("", "")]);
+        request.limits.time_ms = constants::MAX_REQUEST_TIME_MS;
+
+        assert_eq!(
+            request_deadline(&request, Some(CompileLimits::default())),
+            Duration::from_millis(constants::MAX_REQUEST_TIME_MS)
+        );
+    }
 }
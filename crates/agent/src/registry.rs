@@ -0,0 +1,123 @@
+use crate::{
+    cache::OutputCache,
+    engine::{Engine, JudgeError},
+    handler::{CompileLimits, Handler},
+};
+use futures::future::BoxFuture;
+use shared::rpc::{JudgeRequest, JudgeResponse, Language};
+use std::{collections::HashMap, sync::Arc};
+
+type JudgeFuture = BoxFuture<'static, Result<JudgeResponse, JudgeError>>;
+type JudgeFn = Box<dyn Fn(JudgeRequest) -> JudgeFuture + Send + Sync>;
+
+/// Maps each language to the handler judging it
+///
+/// Every handler shares the registry's [`OutputCache`].
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<Language, (JudgeFn, CompileLimits)>,
+    cache: Arc<OutputCache>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler judging `language`, replacing any previous one
+    pub fn register<H>(&mut self, language: Language, handler: H, compile_limits: CompileLimits)
+    where
+        H: Handler + Clone + 'static,
+    {
+        let cache = self.cache.clone();
+        let judge = move |request| -> JudgeFuture {
+            Box::pin(Engine::judge(
+                handler.clone(),
+                request,
+                compile_limits,
+                cache.clone(),
+            ))
+        };
+        self.handlers
+            .insert(language, (Box::new(judge), compile_limits));
+    }
+
+    /// Limits the handler registered for `language` compiles under
+    pub fn compile_limits(&self, language: Language) -> Option<CompileLimits> {
+        self.handlers
+            .get(&language)
+            .map(|&(_, compile_limits)| compile_limits)
+    }
+
+    /// Judge a request with the handler registered for its language
+    pub fn dispatch(&self, request: JudgeRequest) -> JudgeFuture {
+        match self.handlers.get(&request.language) {
+            Some((judge, _)) => judge(request),
+            None => {
+                let language = request.language;
+                Box::pin(async move { Err(JudgeError::UnsupportedLanguage(language)) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{EchoHandler, request};
+    use shared::rpc::{CaseVerdict, JudgeResult};
+
+    fn registry() -> HandlerRegistry {
+        let mut registry = HandlerRegistry::new();
+        registry.register(
+            Language::Python,
+            EchoHandler::default(),
+            CompileLimits::default(),
+        );
+        registry
+    }
+
+    async fn verdicts(registry: &HandlerRegistry, request: JudgeRequest) -> Vec<CaseVerdict> {
+        match registry.dispatch(request).await.unwrap().result {
+            JudgeResult::PerCase { cases } => cases.into_iter().map(|case| case.verdict).collect(),
+            result => panic!("Unexpected result {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatches_to_the_language_handler() {
+        let request = request(Language::Python, &[("42\n", "42"), ("42\n", "43")]);
+
+        assert_eq!(
+            verdicts(&registry(), request).await,
+            [CaseVerdict::Accepted, CaseVerdict::WrongAnswer]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unregistered_languages() {
+        let err = registry()
+            .dispatch(request(Language::Cpp, &[("", "")]))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            JudgeError::UnsupportedLanguage(Language::Cpp)
+        ));
+        assert!(!err.is_fatal());
+    }
+
+    #[test]
+    fn keeps_the_compile_limits_of_each_language() {
+        let mut registry = registry();
+        let limits = CompileLimits {
+            time_ms: 5,
+            memory_kib: 6,
+        };
+        registry.register(Language::C, EchoHandler::default(), limits);
+
+        assert_eq!(registry.compile_limits(Language::C).unwrap().time_ms, 5);
+        assert!(registry.compile_limits(Language::Cpp).is_none());
+    }
+}
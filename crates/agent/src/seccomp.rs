@@ -1,72 +1,137 @@
-use libseccomp::{ScmpAction, ScmpFilterContext, ScmpSyscall, error::SeccompError};
+use libseccomp::{
+    ScmpAction, ScmpArgCompare, ScmpCompareOp, ScmpFilterContext, ScmpSyscall, error::SeccompError,
+};
 use std::io;
 
 fn seccomp_to_io_error(e: SeccompError) -> io::Error {
     io::Error::other(e)
 }
 
+/// Load a filter that allows everything except `syscalls`, which fail with `EPERM`
+fn block_syscalls(syscalls: &[&str]) -> io::Result<()> {
+    let mut filter = ScmpFilterContext::new(ScmpAction::Allow).map_err(seccomp_to_io_error)?;
+
+    for syscall_name in syscalls {
+        filter
+            .add_rule(
+                ScmpAction::Errno(libc::EPERM),
+                ScmpSyscall::from_name(syscall_name).unwrap(),
+            )
+            .map_err(seccomp_to_io_error)?;
+    }
+
+    filter.load().map_err(seccomp_to_io_error)?;
+    Ok(())
+}
+
+// List of syscalls blocked for compilers and interpreters:
+// - Ownership: chown family - compilers only ever chmod their own output
+// - Identity: setuid/setgid/capset - privilege changes
+// - System: mount/reboot/kexec - system-level operations
+// - Debugging: ptrace/process_vm_writev - tampering with other processes
+// - Network: socket/connect/bind/listen - network access
+const COMPILE_BLOCKED_SYSCALLS: [&str; 31] = [
+    "chown",
+    "fchown",
+    "lchown",
+    "fchownat",
+    "setuid",
+    "setgid",
+    "setreuid",
+    "setregid",
+    "setgroups",
+    "setresuid",
+    "setresgid",
+    "capset",
+    "mount",
+    "umount2",
+    "pivot_root",
+    "swapon",
+    "swapoff",
+    "reboot",
+    "kexec_load",
+    "kexec_file_load",
+    "perf_event_open",
+    "bpf",
+    "ptrace",
+    "process_vm_writev",
+    "socket",
+    "socketpair",
+    "connect",
+    "accept",
+    "accept4",
+    "bind",
+    "listen",
+];
+
+/// Seccomp filter installed in a judged program before `exec`, chosen by its handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeccompProfile {
+    /// No filter, for test programs that spawn nothing
+    #[cfg(test)]
+    Unconfined,
+    /// [`SeccompFilter::apply_strict_filter`], for statically linked native programs
+    Strict,
+    /// [`SeccompFilter::apply_compile_filter`], for interpreters and checkers
+    Compile,
+    /// [`SeccompFilter::apply_jvm_filter`]
+    Jvm,
+}
+
+impl SeccompProfile {
+    /// Load the filter into the current process, about to exec the string at `program`
+    ///
+    /// Every filter is stacked on [`SeccompFilter::apply_signal_filter`], loaded first as the
+    /// strict filter forbids loading another one.
+    pub fn apply(self, program: *const u8) -> io::Result<()> {
+        match self {
+            #[cfg(test)]
+            SeccompProfile::Unconfined => Ok(()),
+            SeccompProfile::Strict => {
+                SeccompFilter::apply_signal_filter()?;
+                SeccompFilter::apply_strict_filter(program)
+            }
+            SeccompProfile::Compile => {
+                SeccompFilter::apply_signal_filter()?;
+                SeccompFilter::apply_compile_filter()
+            }
+            SeccompProfile::Jvm => {
+                SeccompFilter::apply_signal_filter()?;
+                SeccompFilter::apply_jvm_filter()
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SeccompFilter;
 
 impl SeccompFilter {
-    /// Applies a basic seccomp filter that blocks dangerous syscalls.
+    /// Applies a filter confining signals and memory access to the current process.
     ///
-    /// Block specific syscalls
-    pub fn apply_basic_filter() -> io::Result<()> {
+    /// Judged programs share one user, so without it they could kill, or read the memory of,
+    /// each other. Signals aimed at the process itself, or its threads, are still delivered.
+    pub fn apply_signal_filter() -> io::Result<()> {
         let mut filter = ScmpFilterContext::new(ScmpAction::Allow).map_err(seccomp_to_io_error)?;
+        let pid = unsafe { libc::getpid() } as u64;
+        let not_self = [ScmpArgCompare::new(0, ScmpCompareOp::NotEqual, pid)];
 
-        // List of dangerous syscalls to block:
-        // - File ops: open/creat/unlink/rmdir/mkdir - file creation/deletion
-        // - Permission: chmod/chown/setuid/setgid - privilege changes
-        // - System: mount/reboot/kexec - system-level operations
-        // - Privilege: capset/ptrace - capability/ptrace debugging
-        // - Network: socket/connect/bind/listen - network access
-        let blocked_syscalls = [
-            "open",
-            "openat",
-            "creat",
-            "unlink",
-            "unlinkat",
-            "rmdir",
-            "mkdir",
-            "mkdirat",
-            "chmod",
-            "fchmod",
-            "fchmodat",
-            "chown",
-            "fchown",
-            "lchown",
-            "fchownat",
-            "setuid",
-            "setgid",
-            "setreuid",
-            "setregid",
-            "setgroups",
-            "setresuid",
-            "setresgid",
-            "capset",
-            "mount",
-            "umount2",
-            "pivot_root",
-            "swapon",
-            "swapoff",
-            "reboot",
-            "kexec_load",
-            "kexec_file_load",
-            "perf_event_open",
-            "bpf",
-            "ptrace",
-            "process_vm_writev",
-            "socket",
-            "socketpair",
-            "connect",
-            "accept",
-            "accept4",
-            "bind",
-            "listen",
-        ];
-
-        for syscall_name in blocked_syscalls {
+        for syscall_name in [
+            "kill",
+            "tkill",
+            "tgkill",
+            "rt_sigqueueinfo",
+            "rt_tgsigqueueinfo",
+        ] {
+            filter
+                .add_rule_conditional(
+                    ScmpAction::Errno(libc::EPERM),
+                    ScmpSyscall::from_name(syscall_name).unwrap(),
+                    &not_self,
+                )
+                .map_err(seccomp_to_io_error)?;
+        }
+        for syscall_name in ["process_vm_readv", "pidfd_open", "pidfd_send_signal"] {
             filter
                 .add_rule(
                     ScmpAction::Errno(libc::EPERM),
@@ -79,10 +144,32 @@ impl SeccompFilter {
         Ok(())
     }
 
+    /// Applies a filter for running compilers and interpreters.
+    ///
+    /// Both need the file system, only network, identity and system syscalls are blocked.
+    /// Seccomp cannot filter by path, confining writes to the work directory is up to the caller.
+    pub fn apply_compile_filter() -> io::Result<()> {
+        block_syscalls(&COMPILE_BLOCKED_SYSCALLS)
+    }
+
+    /// Applies the compile filter, but allowing `socketpair`, for `javac` and `java`.
+    ///
+    /// The JVM sets up its file channels with a unix socket pair, which never reaches the network.
+    pub fn apply_jvm_filter() -> io::Result<()> {
+        let blocked_syscalls: Vec<&str> = COMPILE_BLOCKED_SYSCALLS
+            .into_iter()
+            .filter(|&syscall| syscall != "socketpair")
+            .collect();
+
+        block_syscalls(&blocked_syscalls)
+    }
+
     /// Applies a stricter whitelist-based filter.
     ///
-    /// Only allowed specify syscalls
-    pub fn apply_strict_filter() -> io::Result<()> {
+    /// Only allowed specify syscalls. Nothing can be opened, so the program must be statically
+    /// linked; it cannot start threads either. `execve` is only allowed with `program` as its
+    /// path, the very pointer the caller is about to exec, so the program cannot exec another.
+    pub fn apply_strict_filter(program: *const u8) -> io::Result<()> {
         let mut filter =
             ScmpFilterContext::new(ScmpAction::Errno(libc::EPERM)).map_err(seccomp_to_io_error)?;
 
@@ -92,6 +179,7 @@ impl SeccompFilter {
         // - Process: exit/exit_group - process termination
         // - Signals: rt_sigaction/rt_sigprocmask/rt_sigreturn - signal handling
         // - Info: getpid/getuid/fstat - process info queries
+        // - Startup: newfstatat/rseq - libc initialization
         let allowed_syscalls = [
             "read",
             "write",
@@ -126,6 +214,12 @@ impl SeccompFilter {
             "gettimeofday",
             "time",
             "getrandom",
+            "gettid",
+            "newfstatat",
+            "rseq",
+            "madvise",
+            "mremap",
+            "sigaltstack",
         ];
 
         for syscall_name in allowed_syscalls {
@@ -137,6 +231,25 @@ impl SeccompFilter {
                 .map_err(seccomp_to_io_error)?;
         }
 
+        // `abort` and `raise` signal the process itself
+        let pid = unsafe { libc::getpid() } as u64;
+        for syscall_name in ["tkill", "tgkill"] {
+            filter
+                .add_rule_conditional(
+                    ScmpAction::Allow,
+                    ScmpSyscall::from_name(syscall_name).unwrap(),
+                    &[ScmpArgCompare::new(0, ScmpCompareOp::Equal, pid)],
+                )
+                .map_err(seccomp_to_io_error)?;
+        }
+        filter
+            .add_rule_conditional(
+                ScmpAction::Allow,
+                ScmpSyscall::from_name("execve").unwrap(),
+                &[ScmpArgCompare::new(0, ScmpCompareOp::Equal, program as u64)],
+            )
+            .map_err(seccomp_to_io_error)?;
+
         filter.load().map_err(seccomp_to_io_error)?;
         Ok(())
     }
@@ -0,0 +1,91 @@
+use crate::constants;
+use shared::rpc::CaseData;
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Content-addressed case store, see [`CaseData::Stored`]
+///
+/// Loaded blobs are cached, so data shared between cases is only read once.
+#[derive(Debug)]
+pub struct CaseStore {
+    root: PathBuf,
+    cache: HashMap<String, Arc<str>>,
+}
+
+impl CaseStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Make sure the store at `root` cannot be read by judged programs, if it exists
+    ///
+    /// Expected outputs live there. A read-only store that cannot be restricted is accepted only
+    /// if it already is private to its owner, and that owner is not the sandbox user.
+    pub fn restrict_access(root: &Path) -> io::Result<()> {
+        let metadata = match std::fs::metadata(root) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if std::fs::set_permissions(root, std::fs::Permissions::from_mode(0o700)).is_ok() {
+            return Ok(());
+        }
+
+        if metadata.mode() & 0o077 != 0 || metadata.uid() == constants::SANDBOX_UID {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Case store {} is readable by judged programs",
+                    root.display()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Resolve case data into its content
+    pub async fn resolve(&mut self, data: &CaseData) -> Result<Arc<str>, StoreError> {
+        let id = match data {
+            CaseData::Inline(content) => return Ok(Arc::from(content.as_str())),
+            CaseData::Stored(id) => id,
+        };
+
+        if let Some(content) = self.cache.get(id) {
+            return Ok(content.clone());
+        }
+
+        // Ids are file names, anything else could escape the store
+        let is_valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !is_valid {
+            return Err(StoreError::InvalidId(id.clone()));
+        }
+
+        let content: Arc<str> = tokio::fs::read_to_string(self.root.join(id))
+            .await
+            .map_err(|e| StoreError::Io(id.clone(), e))?
+            .into();
+        self.cache.insert(id.clone(), content.clone());
+
+        Ok(content)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Invalid blob id: \"{0}\"")]
+    InvalidId(String),
+    #[error("Cannot read blob \"{0}\": {1}")]
+    Io(String, std::io::Error),
+}
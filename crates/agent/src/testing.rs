@@ -0,0 +1,127 @@
+//! Helpers shared by unit tests
+
+use crate::{
+    handler::{
+        CompileInfo, CompileLimits, ExecuteInfo, ExecuteLimits, ExecutionContext, Handler,
+        HandlerError, ResourceUsage, sandbox,
+    },
+    seccomp::SeccompProfile,
+};
+use shared::rpc::{
+    CaseData, CompareOptions, CompilerOptions, JudgeRequest, Language, RPC_VERSION, ResourceLimits,
+    TestCase,
+};
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+/// Handler whose "program" echoes its input, without spawning anything
+#[derive(Debug, Clone, Default)]
+pub struct EchoHandler {
+    /// Raw wait status of every run, 0 for a successful exit
+    pub wait_status: i32,
+    /// Memory every run reports using
+    pub memory_kib: u64,
+    /// Written to stderr by every run
+    pub stderr: String,
+    /// Print the extra environment as `KEY=VALUE` lines instead of the input
+    pub print_env: bool,
+    /// Processes every run tries to start, failing like the pids cgroup past the limit
+    pub pids: u64,
+    /// Claim a compile step, which then compiles nothing
+    pub needs_compile: bool,
+}
+
+impl Handler for EchoHandler {
+    fn needs_compile(&self) -> bool {
+        self.needs_compile
+    }
+
+    fn seccomp_profile(&self) -> SeccompProfile {
+        SeccompProfile::Unconfined
+    }
+
+    async fn prepare(&self, source_code: &str) -> Result<ExecutionContext, HandlerError> {
+        sandbox::prepare(source_code, "echo").await
+    }
+
+    async fn compile(
+        &self,
+        _context: &ExecutionContext,
+        _options: &CompilerOptions,
+        _limits: &CompileLimits,
+    ) -> Result<Option<CompileInfo>, HandlerError> {
+        Ok(None)
+    }
+
+    async fn execute(
+        &self,
+        context: &ExecutionContext,
+        input_data: &str,
+        limits: &ExecuteLimits,
+    ) -> Result<ExecuteInfo, HandlerError> {
+        if self.pids > limits.pids {
+            return Err(HandlerError::PidLimitExceeded);
+        }
+        let stdout = if self.print_env {
+            context
+                .env
+                .iter()
+                .map(|(key, value)| format!("{key}={value}\n"))
+                .collect()
+        } else {
+            input_data.to_string()
+        };
+        let status_code = ExitStatus::from_raw(self.wait_status);
+
+        Ok(ExecuteInfo {
+            status_code,
+            signal: status_code.signal(),
+            stdout: stdout.into_bytes(),
+            stderr: self.stderr.clone(),
+            resource_usage: ResourceUsage {
+                memory_kib: self.memory_kib,
+                real_time_ms: 1,
+                cpu_time_ms: 1,
+                max_pids: self.pids.max(1),
+            },
+        })
+    }
+
+    async fn cleanup(&self, context: &ExecutionContext) -> Result<(), HandlerError> {
+        sandbox::cleanup(context).await
+    }
+}
+
+/// Request in `language` with a case per `(input, expected output)` pair
+pub fn request(language: Language, cases: &[(&str, &str)]) -> JudgeRequest {
+    JudgeRequest {
+        version: RPC_VERSION,
+        id: 1,
+        language,
+        source_code: String::new(),
+        files: Vec::new(),
+        compiler_options: CompilerOptions::default(),
+        test_cases: cases
+            .iter()
+            .map(|&(input, expected)| TestCase {
+                input_data: CaseData::Inline(input.into()),
+                expected_output: CaseData::Inline(expected.into()),
+                accepted_outputs: None,
+                time_ms_override: None,
+                memory_kib_override: None,
+            })
+            .collect(),
+        limits: ResourceLimits {
+            time_ms: 1000,
+            real_time_ms: None,
+            memory_kib: 64 * 1024,
+            disk_limit_kib: None,
+            stack_limit_kib: None,
+            pid_limit: None,
+            output_budget_bytes: None,
+        },
+        compare: CompareOptions::default(),
+        seed: None,
+        stop_on_first_failure: false,
+        checker: None,
+    }
+}
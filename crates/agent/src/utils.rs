@@ -1,55 +1,259 @@
-use std::{collections::HashMap, str::FromStr};
+use cgroups_rs::fs::{Controller, memory::MemController};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
+/// Parsed `cpu.stat` of a cgroup
+///
+/// Rows beyond the required usage fields are kept in `fields` rather than rejected, their set
+/// depends on the kernel and on which controllers are enabled.
 #[derive(Debug)]
 #[allow(unused)]
 pub struct CpuStats {
     pub usage_usec: u64,
     pub user_usec: u64,
     pub system_usec: u64,
+    /// Enforcement periods elapsed, present if a cpu quota is set
+    pub nr_periods: Option<u64>,
+    /// Periods in which the group was throttled
+    pub nr_throttled: Option<u64>,
+    /// Total time the group was throttled, timing is skewed if non-zero
+    pub throttled_usec: Option<u64>,
+    /// Every numeric row, including the ones above
+    pub fields: HashMap<String, u64>,
 }
 
 impl FromStr for CpuStats {
     type Err = ParseCpuStatsError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut stats = HashMap::new();
+        let mut fields = HashMap::new();
 
         for line in s.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() != 2 {
-                return Err(Self::Err::InvalidRow(line.to_string()));
-            }
-
-            match parts[1].parse::<u64>() {
-                Ok(value) => stats.insert(parts[0], value),
-                Err(_) => return Err(Self::Err::InvalidNumber(parts[1].to_string())),
+            let mut parts = line.split_whitespace();
+            let (Some(key), Some(value), None) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
             };
+
+            match value.parse::<u64>() {
+                Ok(value) => {
+                    fields.insert(key.to_string(), value);
+                }
+                Err(_) if REQUIRED_CPU_FIELDS.contains(&key) => {
+                    return Err(Self::Err::InvalidNumber(value.to_string()));
+                }
+                Err(_) => {}
+            }
         }
 
-        let usage_usec = *stats
-            .get("usage_usec")
-            .ok_or(Self::Err::MissingImportantField("usage_usec"))?;
-        let user_usec = *stats
-            .get("user_usec")
-            .ok_or(Self::Err::MissingImportantField("user_usec"))?;
-        let system_usec = *stats
-            .get("system_usec")
-            .ok_or(Self::Err::MissingImportantField("system_usec"))?;
+        let required = |field: &'static str| {
+            fields
+                .get(field)
+                .copied()
+                .ok_or(Self::Err::MissingImportantField(field))
+        };
 
         Ok(Self {
-            usage_usec,
-            user_usec,
-            system_usec,
+            usage_usec: required("usage_usec")?,
+            user_usec: required("user_usec")?,
+            system_usec: required("system_usec")?,
+            nr_periods: fields.get("nr_periods").copied(),
+            nr_throttled: fields.get("nr_throttled").copied(),
+            throttled_usec: fields.get("throttled_usec").copied(),
+            fields,
         })
     }
 }
 
+const REQUIRED_CPU_FIELDS: [&str; 3] = ["usage_usec", "user_usec", "system_usec"];
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseCpuStatsError {
     #[error("Invalid number: \"{0}\"")]
     InvalidNumber(String),
-    #[error("Invalid row: \"{0}\"")]
-    InvalidRow(String),
     #[error("Missing important field: \"{0}\"")]
     MissingImportantField(&'static str),
 }
+
+/// Total size in bytes of the regular files under `dir`, skipping `excluded` paths
+///
+/// Symlinks are counted by their own size and never followed.
+pub async fn dir_size(dir: &Path, excluded: &[&Path]) -> io::Result<u64> {
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if excluded.contains(&path.as_path()) {
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push(path);
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+/// Peak memory usage in bytes of a cgroup
+///
+/// Reads `memory.peak` directly on cgroup v2, falling back to `max_usage_in_bytes` on v1 or on
+/// kernels without that file (before 5.19).
+pub fn memory_peak_bytes(controller: &MemController, v2: bool) -> u64 {
+    if v2
+        && let Ok(peak) = std::fs::read_to_string(controller.path().join("memory.peak"))
+        && let Some(peak) = parse_memory_peak(&peak)
+    {
+        return peak;
+    }
+
+    controller.memory_stat().max_usage_in_bytes
+}
+
+/// File holding the current memory usage of a cgroup, in bytes
+pub fn memory_usage_path(controller: &MemController, v2: bool) -> PathBuf {
+    let usage_file = if v2 {
+        "memory.current"
+    } else {
+        "memory.usage_in_bytes"
+    };
+
+    controller.path().join(usage_file)
+}
+
+/// Parse the content of a cgroup v2 `memory.peak` file, e.g. `"1052672\n"`
+fn parse_memory_peak(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// Limit the stack size of the current process, meant to be called from `pre_exec`
+pub fn set_stack_limit(bytes: u64) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: bytes,
+        rlim_max: bytes,
+    };
+
+    if unsafe { libc::setrlimit(libc::RLIMIT_STACK, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Switch the current process to `uid` and `gid` for good, dropping supplementary groups first
+///
+/// Meant to be called from `pre_exec`, before any seccomp filter forbids these syscalls.
+pub fn drop_privileges(uid: u32, gid: u32) -> io::Result<()> {
+    unsafe {
+        if libc::setgroups(0, std::ptr::null()) != 0
+            || libc::setgid(gid) != 0
+            || libc::setuid(uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Conventional name of a signal number, e.g. `SIGSEGV` for 11
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return format!("signal {signal}"),
+    };
+
+    name.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpu_stats_with_extra_rows() {
+        let stat = "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\n\
+            core_sched.force_idle_usec 0\nnr_periods 10\nnr_throttled 2\nthrottled_usec 300\n\
+            nr_bursts 0\nburst_usec 0\n";
+        let stats = CpuStats::from_str(stat).unwrap();
+
+        assert_eq!(stats.usage_usec, 1500);
+        assert_eq!(stats.user_usec, 1000);
+        assert_eq!(stats.system_usec, 500);
+        assert_eq!(stats.nr_throttled, Some(2));
+        assert_eq!(stats.throttled_usec, Some(300));
+        assert_eq!(stats.fields.get("nr_bursts"), Some(&0));
+    }
+
+    #[test]
+    fn tolerates_unknown_malformed_rows() {
+        let stat =
+            "usage_usec 1\nuser_usec 1\nsystem_usec 0\nfuture_field -1\nsome row with words\n\n";
+        let stats = CpuStats::from_str(stat).unwrap();
+
+        assert_eq!(stats.nr_periods, None);
+        assert!(!stats.fields.contains_key("future_field"));
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_usage() {
+        assert!(matches!(
+            CpuStats::from_str("usage_usec 1\nuser_usec 1\n"),
+            Err(ParseCpuStatsError::MissingImportantField("system_usec"))
+        ));
+        assert!(matches!(
+            CpuStats::from_str("usage_usec x\nuser_usec 1\nsystem_usec 0\n"),
+            Err(ParseCpuStatsError::InvalidNumber(value)) if value == "x"
+        ));
+    }
+
+    #[test]
+    fn parses_memory_peak() {
+        assert_eq!(parse_memory_peak("1052672\n"), Some(1052672));
+        assert_eq!(parse_memory_peak("0"), Some(0));
+        assert_eq!(parse_memory_peak(""), None);
+        assert_eq!(parse_memory_peak("max\n"), None);
+    }
+
+    #[tokio::test]
+    async fn dir_size_counts_nested_files_but_not_excluded_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("input.cpp");
+        std::fs::write(&source, [0; 100]).unwrap();
+        std::fs::write(dir.path().join("out.txt"), [0; 10]).unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("nested/more.txt"), [0; 5]).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", dir.path().join("link")).unwrap();
+        let link_size = "/etc/passwd".len() as u64;
+
+        let size = dir_size(dir.path(), &[source.as_path()]).await.unwrap();
+        assert_eq!(size, 15 + link_size);
+    }
+}
@@ -1,2 +1,4 @@
+mod crc32;
 pub mod protocol;
 pub mod rpc;
+mod sha256;
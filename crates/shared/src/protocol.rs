@@ -1,27 +1,166 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::crc32;
+use std::{io, net::Shutdown, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    time::{error::Elapsed, timeout},
+};
 use tokio_vsock::VsockStream;
 
+/// Default time allowed to transfer a frame once it has started
+pub const DEFAULT_FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Send data to vsock stream
 ///
+/// A frame is the payload's length and CRC-32, both little endian `u32`, followed by the payload.
+/// Payloads longer than [`u32::MAX`] are rejected with [`io::ErrorKind::InvalidInput`] before
+/// anything is written.
+///
+/// The whole frame must be written within `frame_timeout`, otherwise an [`io::ErrorKind::TimedOut`]
+/// error is returned. On any failure the stream is shut down, since the peer can no longer find
+/// frame boundaries.
+///
 /// Warning: Using private protocol, so do not send data without using this function.
 pub async fn send_data(
     stream: &mut VsockStream,
     data: &[u8],
-    len: u32,
+    frame_timeout: Duration,
 ) -> Result<(), std::io::Error> {
-    stream.write_u32_le(len).await?;
-    stream.write_all(data).await?;
+    let len = u32::try_from(data.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Frame of {} bytes is too large", data.len()),
+        )
+    })?;
 
-    Ok(())
+    let result = timeout(frame_timeout, async {
+        stream.write_u32_le(len).await?;
+        stream.write_u32_le(crc32::checksum(data)).await?;
+        stream.write_all(data).await
+    })
+    .await;
+
+    finish_frame(stream, result)
 }
 
 /// Receive data from vsock stream
 ///
+/// Waits for the next frame indefinitely, but once its length has arrived the body must follow
+/// within `frame_timeout`. Frames announcing more than `max_len` bytes are rejected with
+/// [`io::ErrorKind::InvalidData`] before anything is allocated, as are frames failing their
+/// checksum. On any failure the stream is shut down, like [`send_data`].
+///
 /// Warning: Using private protocol, so do not receive data without using this function.
-pub async fn receive_data(stream: &mut VsockStream) -> Result<Vec<u8>, std::io::Error> {
+pub async fn receive_data(
+    stream: &mut VsockStream,
+    max_len: u32,
+    frame_timeout: Duration,
+) -> Result<Vec<u8>, std::io::Error> {
     let len = stream.read_u32_le().await?;
-    let mut buf = vec![0; len as usize];
-    stream.read_exact(&mut buf).await?;
+    let result = read_body(stream, len, max_len, frame_timeout).await;
+
+    finish_frame(stream, result)
+}
+
+/// Read the rest of a frame announcing `len` bytes, see [`receive_data`]
+async fn read_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    len: u32,
+    max_len: u32,
+    frame_timeout: Duration,
+) -> Result<io::Result<Vec<u8>>, Elapsed> {
+    if len > max_len {
+        return Ok(Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {len} bytes exceeds the limit of {max_len} bytes"),
+        )));
+    }
+
+    timeout(frame_timeout, async {
+        let checksum = reader.read_u32_le().await?;
+        let mut buf = vec![0; len as usize];
+        reader.read_exact(&mut buf).await?;
+
+        if crc32::checksum(&buf) != checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Frame checksum mismatch",
+            ));
+        }
+
+        Ok(buf)
+    })
+    .await
+}
+
+/// Flatten a timed frame transfer, shutting the stream down if it failed half-way
+fn finish_frame<T>(stream: &VsockStream, result: Result<io::Result<T>, Elapsed>) -> io::Result<T> {
+    let result = result.unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Frame transfer timed out",
+        ))
+    });
+
+    if result.is_err() {
+        // Best effort, the original error is more useful than a shutdown failure
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checksum and payload of a frame, following its length
+    fn body(payload: &[u8]) -> Vec<u8> {
+        let mut body = crc32::checksum(payload).to_le_bytes().to_vec();
+        body.extend_from_slice(payload);
+        body
+    }
+
+    async fn read(body: &[u8], len: u32, max_len: u32) -> io::Result<Vec<u8>> {
+        let mut reader = body;
+        read_body(&mut reader, len, max_len, DEFAULT_FRAME_TIMEOUT)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_a_frame_within_the_limit() {
+        assert_eq!(read(&body(b"hello"), 5, 5).await.unwrap(), b"hello");
+        assert_eq!(read(&body(b""), 0, 0).await.unwrap(), b"");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_oversized_length_before_reading() {
+        // The body is never looked at, nothing of that size is allocated
+        let err = read(&[], u32::MAX, 64).await.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("exceeds the limit of 64 bytes"));
+    }
+
+    #[tokio::test]
+    async fn rejects_corrupted_and_truncated_frames() {
+        let mut corrupted = body(b"hello");
+        corrupted[4] ^= 1;
+        let err = read(&corrupted, 5, 64).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = read(&body(b"hell"), 5, 64).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn times_out_on_stalled_frames() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        // Half of the body arrives, the peer then stalls without closing
+        writer.write_all(&body(b"hello")[..6]).await.unwrap();
 
-    Ok(buf)
+        let result = read_body(&mut reader, 5, 64, Duration::from_millis(50)).await;
+        assert!(result.is_err());
+        drop(writer);
+    }
 }
@@ -1,33 +1,162 @@
+/// RPC schema version
+///
+/// Bump whenever the layout of [`JudgeRequest`] or [`JudgeResponse`] changes: postcard is
+/// order-sensitive and would otherwise silently misinterpret messages from a mismatched peer.
+/// An agent receiving a request of another version answers with a fatal
+/// [`JudgeResult::InternalError`] of id 0, carrying its own version, and closes the connection.
+pub const RPC_VERSION: u16 = 26;
+
+/// Decode a postcard encoded RPC message, checking its schema version first
+///
+/// Every message starts with its version, so it is read before the rest of the layout is trusted.
+pub fn decode<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, DecodeError> {
+    let (version, _) = postcard::take_from_bytes::<u16>(data)?;
+    if version != RPC_VERSION {
+        return Err(DecodeError::VersionMismatch {
+            expected: RPC_VERSION,
+            found: version,
+        });
+    }
+
+    Ok(postcard::from_bytes(data)?)
+}
+
+/// Environment variable carrying [`JudgeRequest::seed`] to the judged program
+pub const SEED_ENV_VAR: &str = "JUDGE_SEED";
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("RPC version mismatch: expected {expected}, found {found}")]
+    VersionMismatch { expected: u16, found: u16 },
+    #[error("{0}")]
+    Postcard(#[from] postcard::Error),
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum JudgeResult {
     Accepted {
         cpu_time_ms: u64,
         real_time_ms: u64,
         memory_kib: u64,
+        /// Highest number of live processes and threads in any case
+        max_pids: u64,
+        /// Number of test cases evaluated
+        cases_run: usize,
     },
     WrongAnswer {
+        case_index: usize,
         expected_output: String,
         actual_output: String,
+        /// Extra explanation of the mismatch, if any
+        hint: Option<String>,
     },
     RuntimeError {
-        actual_output: String,
+        case_index: usize,
+        stdout: String,
+        stderr: String,
+        /// Exit code, none if the program was killed by a signal
+        exit_code: Option<i32>,
+        /// Name of the terminating signal, e.g. `SIGSEGV`
+        signal: Option<String>,
+        kind: RuntimeErrorKind,
+        /// Human readable cause, e.g. `Killed by signal SIGSEGV (11)`
         error_message: String,
     },
     CompilationError {
         compiler_message: String,
+        /// Compiler exit code, none if it was killed by a signal
+        exit_code: Option<i32>,
+        /// Name of the signal that killed the compiler, usually a compiler crash rather than a
+        /// fault of the submission
+        signal: Option<String>,
     },
     InternalError {
         error_message: String,
     },
+    /// `case_index` is none if the limit was exceeded outside a test case (e.g. while compiling)
+    TimeLimitExceeded {
+        case_index: Option<usize>,
+        /// Which of the limits was breached
+        limit: TimeLimit,
+    },
+    /// `case_index` is none if the limit was exceeded outside a test case (e.g. while compiling)
+    MemoryLimitExceeded {
+        case_index: Option<usize>,
+    },
+    /// `case_index` is none if the limit was exceeded outside a test case (e.g. while compiling)
+    OutputLimitExceeded {
+        case_index: Option<usize>,
+    },
+    /// `case_index` is none if the limit was exceeded outside a test case (e.g. while compiling)
+    DiskLimitExceeded {
+        case_index: Option<usize>,
+    },
+    /// `case_index` is none if the limit was exceeded outside a test case (e.g. while compiling)
+    PidLimitExceeded {
+        case_index: Option<usize>,
+    },
+    PresentationError {
+        case_index: usize,
+    },
+    /// Outcome of every test case, see [`JudgeRequest::stop_on_first_failure`]
+    PerCase {
+        cases: Vec<CaseOutcome>,
+    },
+}
+
+/// Outcome of a single test case in [`JudgeResult::PerCase`]
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct CaseOutcome {
+    pub case_index: usize,
+    pub verdict: CaseVerdict,
+    /// Zero if the program was stopped before its usage was measured
+    pub cpu_time_ms: u64,
+    /// Zero if the program was stopped before its usage was measured
+    pub memory_kib: u64,
+    /// Start of the program's stdout
+    pub output: String,
+}
+
+/// Verdict of a single test case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CaseVerdict {
+    Accepted,
+    WrongAnswer,
+    RuntimeError,
     TimeLimitExceeded,
     MemoryLimitExceeded,
     OutputLimitExceeded,
+    DiskLimitExceeded,
+    PidLimitExceeded,
     PresentationError,
 }
 
+/// Time limit breached by a [`JudgeResult::TimeLimitExceeded`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum TimeLimit {
+    /// Cpu time, see [`ResourceLimits::time_ms`]
+    CpuTime,
+    /// Wall-clock time, see [`ResourceLimits::real_time_ms`], e.g. a program sleeping or blocked
+    RealTime,
+}
+
+/// Cause of a [`JudgeResult::RuntimeError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum RuntimeErrorKind {
+    /// Exited normally with a non-zero code
+    NonZeroExit,
+    /// Segmentation fault, most often a stack overflow from deep recursion
+    SegmentationFault,
+    /// Killed by any other signal
+    Signaled,
+}
+
 /// Judge request
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct JudgeRequest {
+    /// RPC schema version, must be [`RPC_VERSION`]
+    pub version: u16,
+
     /// RPC Id
     pub id: usize,
 
@@ -37,27 +166,161 @@ pub struct JudgeRequest {
     /// Source code
     pub source_code: String,
 
+    /// Further files written next to [`JudgeRequest::source_code`], e.g. headers
+    ///
+    /// Compiled languages build every translation unit among them together with the main source.
+    /// Names are plain file names, anything with a path separator is rejected.
+    pub files: Vec<SourceFile>,
+
+    /// Compiler options, ignored by languages without a compile step
+    pub compiler_options: CompilerOptions,
+
     /// Test cases
     pub test_cases: Vec<TestCase>,
 
     /// Resource limits
     pub limits: ResourceLimits,
+
+    /// Output comparison options
+    pub compare: CompareOptions,
+
+    /// Seed passed to the program in [`SEED_ENV_VAR`], none to leave it unset
+    ///
+    /// Only a convention for reproducing randomized solutions: `getrandom`, `/dev/urandom` and the
+    /// clock are not affected.
+    pub seed: Option<u64>,
+
+    /// Stop at the first failing case with its verdict, true unless partial scoring is needed
+    ///
+    /// If false, every case runs and the result is [`JudgeResult::PerCase`], unless the
+    /// submission fails as a whole, e.g. with a compilation error.
+    pub stop_on_first_failure: bool,
+
+    /// Checker judging outputs instead of the built-in comparison, none to compare with
+    /// [`JudgeRequest::compare`]
+    pub checker: Option<Checker>,
+}
+
+/// Custom checker (special judge)
+///
+/// Run as `checker <input> <expected> <actual>` with paths to files holding each. Exit codes
+/// follow testlib:
+/// - 0 accepts the output
+/// - 2 is a [`JudgeResult::PresentationError`]
+/// - 3 is a failure of the checker itself, as is being killed by a signal or hitting a limit,
+///   reported as a [`JudgeResult::InternalError`]
+/// - any other code, usually 1, rejects the output as a [`JudgeResult::WrongAnswer`], with the
+///   checker's stdout as its hint
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub enum Checker {
+    /// Path of an executable inside the VM
+    Executable(String),
+
+    /// C++ source, compiled before judging
+    CppSource(String),
+}
+
+impl JudgeRequest {
+    /// Stable SHA-256 fingerprint of the submission and its test data
+    ///
+    /// Covers the language, source files, compiler options, seed and every case's input and
+    /// accepted outputs, but not the id, version, limits or comparison options, so rejudging the
+    /// same work under different limits keeps the fingerprint.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        #[derive(serde::Serialize)]
+        struct Fingerprinted<'a> {
+            scheme: &'static str,
+            language: Language,
+            source_code: &'a str,
+            files: &'a [SourceFile],
+            compiler_options: &'a CompilerOptions,
+            seed: Option<u64>,
+            test_cases: Vec<(&'a CaseData, &'a CaseData, &'a Option<Vec<CaseData>>)>,
+        }
+
+        let fingerprinted = Fingerprinted {
+            scheme: "judge-core-fingerprint-v2",
+            language: self.language,
+            source_code: &self.source_code,
+            files: &self.files,
+            compiler_options: &self.compiler_options,
+            seed: self.seed,
+            test_cases: self
+                .test_cases
+                .iter()
+                .map(|case| {
+                    (
+                        &case.input_data,
+                        &case.expected_output,
+                        &case.accepted_outputs,
+                    )
+                })
+                .collect(),
+        };
+
+        // Serializing plain owned data into a vec cannot fail
+        let encoded = postcard::to_allocvec(&fingerprinted).expect("fingerprint encoding");
+        crate::sha256::digest(&encoded)
+    }
 }
 
 /// Available languages
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 pub enum Language {
     Cpp,
+    C,
+    Python,
+    /// The public class must be named `Main`
+    Java,
+}
+
+/// File of a multi-file submission
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SourceFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// Compiler options
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct CompilerOptions {
+    /// Optimization level
+    pub optimization: OptLevel,
+
+    /// Emit debug information, mostly useful together with [`OptLevel::O0`]
+    pub debug_info: bool,
+
+    /// Extra flags such as `-std=c++20`, passed after the defaults so they take precedence
+    ///
+    /// Only `-std=`, `-D`, `-U`, `-W<warning>`, `-f[no-]sanitize=`, `-g*`, `-O*` and `-march=`
+    /// are accepted. Everything else is rejected, including `-Wl,`, `-Wa,` and `-Wp,`
+    /// pass-throughs, since it could load code or read and write files outside the work directory.
+    pub extra_flags: Vec<String>,
+}
+
+/// Compiler optimization level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum OptLevel {
+    O0,
+    O1,
+    #[default]
+    O2,
+    O3,
+    Os,
 }
 
 impl JudgeResult {
     /// Convert into judge response, is_fatal_error if internal error
+    ///
+    /// Internal errors are reserved for agent/infrastructure failures, never for the submission
     pub fn into_judge_response(self, id: usize) -> JudgeResponse {
         let is_fatal = matches!(self, Self::InternalError { error_message: _ });
 
         JudgeResponse {
+            version: RPC_VERSION,
             id,
             is_fatal_error: Some(is_fatal),
+            compile_stats: None,
             result: self,
         }
     }
@@ -66,32 +329,230 @@ impl JudgeResult {
 /// Judge response
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct JudgeResponse {
+    /// RPC schema version, always [`RPC_VERSION`]
+    pub version: u16,
+
     /// RPC Id
     pub id: usize,
 
     /// If true, this error is fatal
     pub is_fatal_error: Option<bool>,
 
+    /// Resources used by the compiler, none if nothing was compiled
+    pub compile_stats: Option<CompileStats>,
+
     /// Judge result type
     pub result: JudgeResult,
 }
 
+/// Resource usage of a compilation
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub struct CompileStats {
+    /// Cpu time in milliseconds
+    pub time_ms: u64,
+
+    /// Peak memory usage in KiB
+    pub memory_kib: u64,
+}
+
 /// Resource limits
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ResourceLimits {
-    /// Time limit in milliseconds
+    /// Cpu time limit in milliseconds
     pub time_ms: u64,
 
+    /// Wall-clock time limit in milliseconds, none for a multiple of the case's cpu time limit
+    ///
+    /// The program is killed once it elapses, even while using no cpu time.
+    pub real_time_ms: Option<u64>,
+
     /// Memory limit in KiB
     pub memory_kib: u64,
+
+    /// Limit in KiB on files written into the work directory, none for no quota
+    pub disk_limit_kib: Option<u64>,
+
+    /// Stack size limit in KiB, none to allow the whole memory limit
+    pub stack_limit_kib: Option<u64>,
+
+    /// Limit on live processes and threads, including the program itself, none for the agent's
+    /// default
+    pub pid_limit: Option<u64>,
+
+    /// Limit in bytes on stdout and stderr across all test cases, none for no limit
+    pub output_budget_bytes: Option<u64>,
+}
+
+/// Output comparison options
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CompareOptions {
+    /// Normalization applied to both outputs before comparing
+    pub mode: CompareMode,
+
+    /// If false, outputs are compared after lowercasing both sides
+    pub case_sensitive: bool,
+
+    /// On a wrong answer, hint when stderr holds the expected output instead of stdout
+    pub stderr_hint: bool,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::default(),
+            case_sensitive: true,
+            stderr_hint: false,
+        }
+    }
+}
+
+/// Output comparison mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum CompareMode {
+    /// Strip leading and trailing whitespace of the whole output
+    #[default]
+    Trimmed,
+
+    /// Strip trailing whitespace of every line and drop trailing blank lines
+    ///
+    /// Leading whitespace and the rest of the line structure are preserved.
+    LineTrimmed,
+
+    /// Parse both outputs as whitespace separated numbers and compare them element-wise
+    ///
+    /// Both must contain the same count of numbers. Pairs of integers are compared exactly if
+    /// `int_exact`, everything else as floats within `float_eps`, absolute or relative to the
+    /// expected value. Case sensitivity does not apply.
+    Numeric { int_exact: bool, float_eps: f64 },
+
+    /// Compare the raw bytes of stdout with the expected output, nothing is stripped
+    ///
+    /// Output that is not valid UTF-8 never matches. Without case sensitivity, only ASCII letters
+    /// are folded.
+    Exact,
 }
 
 /// Test case
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct TestCase {
     /// Input data
-    pub input_data: String,
+    pub input_data: CaseData,
 
     /// Expected output
-    pub expected_output: String,
+    pub expected_output: CaseData,
+
+    /// Other outputs accepted besides [`TestCase::expected_output`], for problems with a few
+    /// valid answers
+    pub accepted_outputs: Option<Vec<CaseData>>,
+
+    /// Time limit in milliseconds for this case, overriding [`ResourceLimits::time_ms`]
+    pub time_ms_override: Option<u64>,
+
+    /// Memory limit in KiB for this case, overriding [`ResourceLimits::memory_kib`]
+    pub memory_kib_override: Option<u64>,
+}
+
+/// Test case data
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub enum CaseData {
+    /// Embedded in the request
+    Inline(String),
+
+    /// Id of a blob provisioned into the agent's case store
+    ///
+    /// The store is a read-only directory inside the VM (e.g. a drive attached by the host) holding
+    /// one file per blob, named by its id. Ids are conventionally the hex SHA-256 of the content,
+    /// so cases sharing data reference the same blob and it is only read once per request.
+    Stored(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> JudgeRequest {
+        JudgeRequest {
+            version: RPC_VERSION,
+            id: 1,
+            language: Language::Cpp,
+            source_code: "int main() {}".into(),
+            files: Vec::new(),
+            compiler_options: CompilerOptions::default(),
+            test_cases: vec![TestCase {
+                input_data: CaseData::Inline("1 2\n".into()),
+                expected_output: CaseData::Inline("3\n".into()),
+                accepted_outputs: None,
+                time_ms_override: None,
+                memory_kib_override: None,
+            }],
+            limits: ResourceLimits {
+                time_ms: 1000,
+                real_time_ms: None,
+                memory_kib: 256 * 1024,
+                disk_limit_kib: None,
+                stack_limit_kib: None,
+                pid_limit: None,
+                output_budget_bytes: None,
+            },
+            compare: CompareOptions::default(),
+            seed: None,
+            stop_on_first_failure: false,
+            checker: None,
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_id_limits_and_comparison() {
+        let mut rejudge = request();
+        rejudge.id = 2;
+        rejudge.limits.time_ms = 2000;
+        rejudge.compare.case_sensitive = false;
+
+        assert_eq!(request().fingerprint(), rejudge.fingerprint());
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_work() {
+        let fingerprint = request().fingerprint();
+
+        let mut flagged = request();
+        flagged.compiler_options.extra_flags.push("-DLOCAL".into());
+        assert_ne!(fingerprint, flagged.fingerprint());
+
+        let mut seeded = request();
+        seeded.seed = Some(7);
+        assert_ne!(fingerprint, seeded.fingerprint());
+
+        let mut expected = request();
+        expected.test_cases[0].expected_output = CaseData::Inline("4\n".into());
+        assert_ne!(fingerprint, expected.fingerprint());
+    }
+
+    #[test]
+    fn decodes_messages_of_the_same_version() {
+        let encoded = postcard::to_allocvec(&request()).unwrap();
+        let decoded: JudgeRequest = decode(&encoded).unwrap();
+
+        assert_eq!(decoded.id, 1);
+        assert_eq!(decoded.source_code, "int main() {}");
+    }
+
+    #[test]
+    fn rejects_other_versions_before_the_layout() {
+        let mut request = request();
+        request.version = RPC_VERSION - 1;
+        let encoded = postcard::to_allocvec(&request).unwrap();
+
+        let err = decode::<JudgeRequest>(&encoded).unwrap_err();
+        assert!(matches!(
+            err,
+            DecodeError::VersionMismatch { expected, found }
+                if expected == RPC_VERSION && found == RPC_VERSION - 1
+        ));
+        // Whatever follows the version is not even looked at
+        assert!(matches!(
+            decode::<JudgeRequest>(&postcard::to_allocvec(&(RPC_VERSION + 1)).unwrap()),
+            Err(DecodeError::VersionMismatch { .. })
+        ));
+    }
 }